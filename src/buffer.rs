@@ -0,0 +1,252 @@
+use crate::encoding::Encoding;
+use crate::highlight::Highlighter;
+use crate::options::{OptionValue, Options};
+use crate::save;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+#[derive(Default)]
+pub struct Line {
+    pub chars: String,
+    // What to show in the sign column next to the line, put there by
+    // whatever wants to mark it, like a list of errors.
+    pub sign: Option<Sign>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Sign {
+    // One or two characters.
+    pub text: String,
+    // The highlight group to show the text in.
+    pub group: String,
+}
+
+// How lines are separated in a file, the `fileformat` option.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FileFormat {
+    Unix,
+    Dos,
+    Mac,
+}
+
+impl FileFormat {
+    pub fn parse(name: &str) -> Option<FileFormat> {
+        match name {
+            "unix" => Some(FileFormat::Unix),
+            "dos" => Some(FileFormat::Dos),
+            "mac" => Some(FileFormat::Mac),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileFormat::Unix => "unix",
+            FileFormat::Dos => "dos",
+            FileFormat::Mac => "mac",
+        }
+    }
+
+    pub fn separator(&self) -> &'static str {
+        match self {
+            FileFormat::Unix => "\n",
+            FileFormat::Dos => "\r\n",
+            FileFormat::Mac => "\r",
+        }
+    }
+}
+
+// How the text of a file is stored: its encoding, whether that starts with
+// a byte order mark, the line separator and whether the last line ends with
+// one.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Format {
+    pub encoding: Encoding,
+    pub bom: bool,
+    pub fileformat: FileFormat,
+    pub eol: bool,
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        Format {
+            encoding: Encoding::Utf8,
+            bom: false,
+            fileformat: FileFormat::Unix,
+            eol: true,
+        }
+    }
+}
+
+// Splits the contents of a file into lines, detecting the line separator. A
+// file is taken to be in DOS format if all of its lines end with CR LF.
+pub fn split_lines(text: &str) -> (Vec<Line>, Format) {
+    if text.is_empty() {
+        return (vec![Line::default()], Format::default());
+    }
+    let fileformat = match text.matches('\n').count() {
+        0 if text.contains('\r') => FileFormat::Mac,
+        0 => FileFormat::Unix,
+        n if n == text.matches("\r\n").count() => FileFormat::Dos,
+        _ => FileFormat::Unix,
+    };
+    let separator = fileformat.separator();
+    let (body, eol) = match text.strip_suffix(separator) {
+        Some(body) => (body, true),
+        None => (text, false),
+    };
+    let lines = body
+        .split(separator)
+        .map(|line| Line {
+            chars: line.to_string(),
+            sign: None,
+        })
+        .collect();
+    let format = Format {
+        fileformat,
+        eol,
+        ..Format::default()
+    };
+    (lines, format)
+}
+
+// What is unusual about the way a file is stored, as shown after its name
+// when it is read or written, like `[latin1] [dos] `.
+pub fn format_tags(format: Format) -> String {
+    let mut tags = String::new();
+    if format.encoding != Encoding::Utf8 {
+        tags.push_str(&format!("[{}] ", format.encoding.name()));
+    }
+    if format.bom {
+        tags.push_str("[BOM] ");
+    }
+    if format.fileformat != FileFormat::Unix {
+        tags.push_str(&format!("[{}] ", format.fileformat.name()));
+    }
+    if !format.eol {
+        tags.push_str("[noeol] ");
+    }
+    tags
+}
+
+pub struct EditorFile {
+    pub lines: Vec<Line>,
+    pub name: String,
+    // Buffers are numbered from 1 in the order they were made, and keep
+    // their number until they are deleted.
+    pub number: usize,
+    pub row_pos: usize,
+    pub col_pos: usize,
+    pub row_scroll_pos: usize,
+    pub col_scroll_pos: usize,
+    pub highlighter: Option<Highlighter>,
+    pub options: Options,
+    // Whether the buffer holds no text at all, which is kept apart from a
+    // single empty line so that only an empty file is written back empty.
+    pub empty: bool,
+    // The number of changes made to the lines, counting up with each one.
+    pub seq_cur: usize,
+    // The change the lines were at when the file was last read or written,
+    // and the format it had then.
+    pub saved_seq: usize,
+    pub saved_format: Format,
+}
+
+impl EditorFile {
+    // An empty buffer without a name.
+    pub fn new(number: usize) -> EditorFile {
+        EditorFile {
+            lines: vec![Line::default()],
+            name: String::new(),
+            number,
+            row_pos: 0,
+            col_pos: 0,
+            row_scroll_pos: 0,
+            col_scroll_pos: 0,
+            highlighter: None,
+            options: Options::default(),
+            empty: true,
+            seq_cur: 0,
+            saved_seq: 0,
+            saved_format: Format::default(),
+        }
+    }
+
+    // The name to show for the buffer.
+    pub fn display_name(&self) -> &str {
+        match self.name.as_str() {
+            "" => "[No Name]",
+            name => name,
+        }
+    }
+
+    // The number of lines in the file, which is 0 for an empty buffer.
+    pub fn line_count(&self) -> usize {
+        match self.empty {
+            true => 0,
+            false => self.lines.len(),
+        }
+    }
+
+    pub fn filetype(&self) -> Option<&str> {
+        match self.options.get("filetype") {
+            Some(OptionValue::String(filetype)) if !filetype.is_empty() => Some(filetype),
+            _ => None,
+        }
+    }
+
+    // Must be called after lines `row..row + old` were replaced by
+    // `row..row + new`.
+    pub fn edited(&mut self, row: usize, old: usize, new: usize) {
+        self.empty = false;
+        self.seq_cur += 1;
+        if let Some(highlighter) = &mut self.highlighter {
+            highlighter.edit(&self.lines, row, old, new);
+        }
+    }
+    // The text of `lines` as written to a file. The last line of the file
+    // only gets a separator with `eol`, and an empty buffer is written as an
+    // empty file.
+    pub fn contents(&self, lines: Range<usize>, format: Format) -> String {
+        if self.empty && lines == (0..self.lines.len()) {
+            return String::new();
+        }
+        let separator = format.fileformat.separator();
+        let last = lines.end == self.lines.len();
+        let mut text = self.lines[lines]
+            .iter()
+            .map(|l| l.chars.as_str())
+            .collect::<Vec<_>>()
+            .join(separator);
+        if !last || format.eol {
+            text.push_str(separator);
+        }
+        text
+    }
+
+    // Writes or appends `lines` to `path`, returning the number of bytes
+    // written. When writing, the original is kept with the extension
+    // `backup` if one is given, and a read-only file is only replaced with
+    // `force`. Appended text never gets a byte order mark.
+    pub fn write(
+        &self,
+        path: &Path,
+        lines: Range<usize>,
+        format: Format,
+        append: bool,
+        backup: Option<&str>,
+        force: bool,
+    ) -> io::Result<usize> {
+        let text = self.contents(lines, format);
+        let bytes = format
+            .encoding
+            .encode(&text, format.bom && !append)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        match append {
+            true => save::append(path, &bytes)?,
+            false => save::write_atomic(path, &bytes, backup, force)?,
+        }
+        Ok(bytes.len())
+    }
+}
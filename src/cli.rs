@@ -0,0 +1,96 @@
+pub use crate::window::Split;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: viprust [arguments] [file ..]
+
+Arguments:
+   --                   Only file names after this
+   -                    Read text from stdin
+   +                    Start at end of file
+   +<lnum>              Start at line <lnum>
+   +/<pattern>          Start at first line matching <pattern>
+   -R                   Readonly mode (like \"view\")
+   -o[N]                Open N windows (default: one for each file)
+   -O[N]                Like -o but split vertically
+   -c <command>         Execute <command> after loading the first file
+   --cmd <command>      Execute <command> before loading any config file
+   -u <config>          Use <config> instead of the default config file
+   --clean              Skip the config file and user syntaxes
+   -h  or  --help       Print this help and exit
+   --version            Print version information and exit";
+
+// What the editor was started with.
+#[derive(Default, PartialEq, Debug)]
+pub struct Args {
+    // `-` reads the text from stdin.
+    pub files: Vec<String>,
+    pub readonly: bool,
+    // Ex commands run before the config is loaded, from `--cmd`, and after
+    // the first file is read, from `-c` and `+`.
+    pub pre_commands: Vec<String>,
+    pub commands: Vec<String>,
+    // How to open the files in windows, and how many, if not one for each.
+    pub split: Option<(Split, Option<usize>)>,
+    // The config file to use instead of the default one. `NONE` uses none.
+    pub config: Option<PathBuf>,
+    pub clean: bool,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    Edit(Args),
+    Help,
+    Version,
+}
+
+// Parses the arguments after the name of the program. The editor is
+// read-only when started as `view`.
+pub fn parse(program: &str, args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut parsed = Args {
+        readonly: program.rsplit('/').next() == Some("view"),
+        ..Args::default()
+    };
+    let mut args = args.into_iter();
+    let mut only_files = false;
+    while let Some(arg) = args.next() {
+        if only_files || arg == "-" || !arg.starts_with(['-', '+']) {
+            parsed.files.push(arg);
+            continue;
+        }
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("Argument missing after: \"{}\"", name))
+        };
+        match arg.as_str() {
+            "--" => only_files = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "--version" => return Ok(Command::Version),
+            "-R" => parsed.readonly = true,
+            "-c" => parsed.commands.push(value(&arg)?),
+            "--cmd" => parsed.pre_commands.push(value(&arg)?),
+            "-u" => parsed.config = Some(PathBuf::from(value(&arg)?)),
+            "--clean" => parsed.clean = true,
+            // `+` is `:$`, `+N` is `:N` and `+/pat` is `:/pat`.
+            "+" => parsed.commands.push("$".to_string()),
+            _ if arg.starts_with('+') => parsed.commands.push(arg[1..].to_string()),
+            _ if arg.starts_with("-o") || arg.starts_with("-O") => {
+                let split = match &arg[..2] {
+                    "-o" => Split::Horizontal,
+                    _ => Split::Vertical,
+                };
+                let count = match &arg[2..] {
+                    "" => None,
+                    count => Some(
+                        count
+                            .parse()
+                            .map_err(|_| format!("Garbage after option argument: \"{}\"", arg))?,
+                    ),
+                };
+                parsed.split = Some((split, count));
+            }
+            _ => return Err(format!("Unknown option argument: \"{}\"", arg)),
+        }
+    }
+    Ok(Command::Edit(parsed))
+}
@@ -0,0 +1,577 @@
+use crate::buffer;
+use crate::config;
+use crate::editor::{Editor, Effect, WriteRequest};
+use crate::encoding::Encoding;
+use crate::keymap::{self, MapCommand};
+use crate::options::{self, OptionValue, SetArg};
+use crate::save;
+use crate::theme;
+use crate::window::Split;
+use regex::Regex;
+use std::iter;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+impl Editor {
+    // Executes an ex command, the text typed after `:`, like `w`, `1,10w
+    // part.rs` or `q!`.
+    pub fn execute(&mut self, command: &str) -> Result<Effect, String> {
+        let (range, rest) = self.parse_range(command.trim_start())?;
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let (name, rest) = rest.split_at(end);
+        let (bang, args) = match rest.strip_prefix('!') {
+            Some(args) => (true, args.trim()),
+            None => (false, rest.trim()),
+        };
+        if range.is_some() && !matches!(name, "" | "w" | "write" | "wq") {
+            return Err("No range allowed".to_string());
+        }
+        match name {
+            // A range on its own moves the cursor to its last line.
+            "" => match range {
+                Some(range) => {
+                    self.file.row_pos = range.end - 1;
+                    self.file.col_pos = 0;
+                    Ok(Effect::Redraw)
+                }
+                None => Ok(Effect::None),
+            },
+            "w" | "write" => self.write_command(range, bang, args, false),
+            "wq" => self.write_command(range, bang, args, true),
+            "x" | "xi" | "xit" | "exi" | "exit" => match self.modified() {
+                true => self.write_command(None, bang, args, true),
+                false => self.quit_window(bang),
+            },
+            "up" | "upd" | "upda" | "updat" | "update" => match self.modified() {
+                true => self.write_command(None, bang, args, false),
+                false => Ok(Effect::None),
+            },
+            "sav" | "save" | "savea" | "saveas" if args.is_empty() => {
+                Err("Argument required".to_string())
+            }
+            "sav" | "save" | "savea" | "saveas" => {
+                if !bang && Path::new(args).exists() {
+                    return Err("File exists (add ! to override)".to_string());
+                }
+                match self.write_command(None, true, args, false)? {
+                    Effect::Write(request) => Ok(Effect::Write(WriteRequest {
+                        rename: true,
+                        ..request
+                    })),
+                    effect => Ok(effect),
+                }
+            }
+            "e" | "ed" | "edi" | "edit" => self.edit_command(bang, args),
+            "q" | "qu" | "qui" | "quit" => self.quit_window(bang),
+            "clo" | "clos" | "close" => {
+                self.close_window(self.window)?;
+                Ok(Effect::Redraw)
+            }
+            "on" | "onl" | "only" => {
+                self.only_window();
+                Ok(Effect::Redraw)
+            }
+            "sp" | "spl" | "spli" | "split" => self.split_command(Split::Horizontal, args),
+            "vs" | "vsp" | "vspl" | "vspli" | "vsplit" => self.split_command(Split::Vertical, args),
+            "new" => self.new_command(Split::Horizontal),
+            "vne" | "vnew" => self.new_command(Split::Vertical),
+            "tabnew" | "tabe" | "tabed" | "tabedi" | "tabedit" => self.tab_command(args),
+            "tabn" | "tabne" | "tabnex" | "tabnext" if args.is_empty() => self.cycle_tab(true),
+            "tabn" | "tabne" | "tabnex" | "tabnext" => {
+                let index = args
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| (1..=self.tab_count()).contains(n))
+                    .ok_or_else(|| format!("Invalid argument: {}", args))?;
+                self.goto_tab(index - 1);
+                Ok(Effect::Redraw)
+            }
+            "tabN" | "tabNe" | "tabNex" | "tabNext" | "tabp" | "tabpr" | "tabpre" | "tabprev"
+            | "tabprevi" | "tabprevio" | "tabpreviou" | "tabprevious" => self.cycle_tab(false),
+            "tabc" | "tabcl" | "tabclo" | "tabclos" | "tabclose" => {
+                self.close_tab()?;
+                Ok(Effect::Redraw)
+            }
+            "tabo" | "tabon" | "tabonl" | "tabonly" => {
+                self.only_tab();
+                Ok(Effect::Redraw)
+            }
+            "ls" | "buffers" | "files" => Ok(Effect::Message(self.list_buffers())),
+            "b" | "bu" | "buf" | "buff" | "buffe" | "buffer" if args.is_empty() => Ok(Effect::None),
+            "b" | "bu" | "buf" | "buff" | "buffe" | "buffer" => {
+                let number = self.find_buffer(args)?;
+                self.switch_buffer(number)?;
+                Ok(Effect::Redraw)
+            }
+            "bn" | "bne" | "bnex" | "bnext" => self.cycle_buffer(true),
+            "bN" | "bNe" | "bNex" | "bNext" | "bp" | "bpr" | "bpre" | "bprev" | "bprevi"
+            | "bprevio" | "bpreviou" | "bprevious" => self.cycle_buffer(false),
+            "bd" | "bde" | "bdel" | "bdele" | "bdelet" | "bdelete" => {
+                self.delete_command(bang, args)
+            }
+            "so" | "source" => self.source((!args.is_empty()).then(|| PathBuf::from(args))),
+            "se" | "set" => self.set_command(args, false),
+            "setl" | "setlocal" => self.set_command(args, true),
+            name if keymap::map_command(&map_name(name, bang)).is_some() => {
+                let (command, modes) = keymap::map_command(&map_name(name, bang)).unwrap();
+                self.map_command(command, modes, args)
+            }
+            "colo" | "colorscheme" if args.is_empty() => {
+                Ok(Effect::Message(self.theme.name.clone()))
+            }
+            "colo" | "colorscheme" => {
+                self.theme = theme::load(args, self.config_dir.as_deref())?;
+                Ok(Effect::Redraw)
+            }
+            _ => Err(format!("Not an editor command: {}", command.trim())),
+        }
+    }
+
+    // Parses the range at the start of a command, like `3`, `.,$` or `%`,
+    // into the lines it covers. Returns the rest of the command as well.
+    fn parse_range<'a>(&self, command: &'a str) -> Result<(Option<Range<usize>>, &'a str), String> {
+        let last = self.file.lines.len().max(1);
+        if let Some(rest) = command.strip_prefix('%') {
+            return Ok((Some(0..last), rest));
+        }
+        let Some((start, rest)) = self.parse_address(command)? else {
+            return Ok((None, command));
+        };
+        let (end, rest) = match rest.strip_prefix(',') {
+            Some(rest) => self.parse_address(rest)?.ok_or("Invalid range")?,
+            None => (start, rest),
+        };
+        if start == 0 || end > last {
+            return Err("Invalid range".to_string());
+        }
+        if start > end {
+            return Err("Backwards range given".to_string());
+        }
+        Ok((Some(start - 1..end), rest))
+    }
+
+    // Parses a line number, `.`, `$` or `/pattern/`, optionally followed by
+    // `+N` or `-N`. Lines are counted from 1.
+    fn parse_address<'a>(&self, text: &'a str) -> Result<Option<(usize, &'a str)>, String> {
+        let digits = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let (line, mut rest) = match text.chars().next() {
+            Some('.') => (self.file.row_pos + 1, &text[1..]),
+            Some('$') => (self.file.lines.len(), &text[1..]),
+            Some('/') => {
+                let (pattern, rest) = text[1..].split_once('/').unwrap_or((&text[1..], ""));
+                (self.search_line(pattern)?, rest)
+            }
+            _ if digits > 0 => {
+                let line = text[..digits].parse().map_err(|_| "Invalid range")?;
+                (line, &text[digits..])
+            }
+            _ => return Ok(None),
+        };
+        let mut line = line as isize;
+        while let Some(sign) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+            let after = &rest[1..];
+            let digits = after
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after.len());
+            let offset: isize = match digits {
+                0 => 1,
+                _ => after[..digits].parse().map_err(|_| "Invalid range")?,
+            };
+            line += if sign == '+' { offset } else { -offset };
+            rest = &after[digits..];
+        }
+        match usize::try_from(line) {
+            Ok(line) => Ok(Some((line, rest))),
+            Err(_) => Err("Invalid range".to_string()),
+        }
+    }
+
+    // The number of the first line after the cursor that matches `pattern`,
+    // wrapping around at the end of the file.
+    fn search_line(&self, pattern: &str) -> Result<usize, String> {
+        let regex = Regex::new(pattern).map_err(|_| format!("Invalid pattern: {}", pattern))?;
+        let count = self.file.lines.len();
+        (1..=count)
+            .map(|i| (self.file.row_pos + i) % count)
+            .find(|&row| regex.is_match(&self.file.lines[row].chars))
+            .map(|row| row + 1)
+            .ok_or_else(|| format!("Pattern not found: {}", pattern))
+    }
+
+    // `:w`, `:w file`, `:w >> file` and `:wq`. Without a range, the whole
+    // file is written.
+    pub fn write_command(
+        &mut self,
+        lines: Option<Range<usize>>,
+        bang: bool,
+        args: &str,
+        quit: bool,
+    ) -> Result<Effect, String> {
+        let (append, name) = match args.strip_prefix(">>") {
+            Some(name) => (true, name.trim()),
+            None => (false, args),
+        };
+        let path = match (name, self.file.name.as_str()) {
+            ("", "") => return Err("No file name".to_string()),
+            ("", name) | (name, _) => PathBuf::from(name),
+        };
+        let whole = 0..self.file.lines.len();
+        let own = save::same_file(&path, Path::new(&self.file.name));
+        if !bang && !append {
+            if own && self.flag("readonly") {
+                return Err("'readonly' option is set (add ! to override)".to_string());
+            }
+            if !own && path.exists() {
+                return Err("File exists (add ! to override)".to_string());
+            }
+            if own && lines.as_ref().is_some_and(|lines| *lines != whole) {
+                return Err("Use ! to write partial buffer".to_string());
+            }
+        }
+        Ok(Effect::Write(WriteRequest {
+            path,
+            lines: lines.unwrap_or(whole),
+            append,
+            quit,
+            force: bang,
+            rename: false,
+        }))
+    }
+
+    // Carries out a write requested by `:w` and friends, returning the message
+    // to show.
+    pub fn write(&mut self, request: &WriteRequest) -> Result<String, String> {
+        let WriteRequest {
+            path,
+            lines,
+            append,
+            force,
+            rename,
+            ..
+        } = request;
+        let backup = self.flag("backup").then(|| self.string("backupext"));
+        let format = self.write_format();
+        let bytes = self
+            .file
+            .write(path, lines.clone(), format, *append, backup, *force)
+            .map_err(|err| format!("Cannot write \"{}\": {}", path.display(), err))?;
+        if *rename {
+            self.file.name = path.display().to_string();
+            self.detect_filetype();
+        }
+        let whole = *lines == (0..self.file.lines.len());
+        if whole && !append && save::same_file(path, Path::new(&self.file.name)) {
+            self.file.saved_seq = self.file.seq_cur;
+            self.file.saved_format = format;
+            self.set_format(format);
+        }
+        let verb = if *append { "appended" } else { "written" };
+        Ok(format!(
+            "\"{}\" {}{}L, {}B {}",
+            path.display(),
+            buffer::format_tags(format),
+            lines.len().min(self.file.line_count()),
+            bytes,
+            verb
+        ))
+    }
+
+    // `:e[dit][!] [++enc=name] [file]`, which reads a file, or the current
+    // file again.
+    fn edit_command(&mut self, bang: bool, args: &str) -> Result<Effect, String> {
+        let mut encoding = None;
+        let mut args = args;
+        while let Some(arg) = args.strip_prefix("++") {
+            let end = arg.find(char::is_whitespace).unwrap_or(arg.len());
+            let name = match arg[..end].split_once('=') {
+                Some(("enc" | "encoding", name)) => name,
+                _ => return Err(format!("Invalid argument: ++{}", &arg[..end])),
+            };
+            encoding =
+                Some(Encoding::parse(name).ok_or_else(|| format!("Invalid encoding: {}", name))?);
+            args = arg[end..].trim_start();
+        }
+        let name = match args {
+            "" if self.file.name.is_empty() => return Err("No file name".to_string()),
+            "" => self.file.name.clone(),
+            name => name.to_string(),
+        };
+        // Another file is edited in a buffer of its own, which is only read
+        // if there is none for it yet.
+        if name != self.file.name {
+            match self.buffers.iter().find(|b| b.name == name) {
+                Some(file) if encoding.is_none() => {
+                    self.switch_buffer(file.number)?;
+                    return Ok(Effect::Redraw);
+                }
+                Some(file) => self.switch_buffer(file.number)?,
+                None => {
+                    self.new_buffer();
+                    return match self.open_file(&name, encoding) {
+                        Ok(message) => Ok(Effect::Message(message)),
+                        Err(err) => {
+                            self.delete_buffer(self.file.number);
+                            Err(format!("Cannot read \"{}\": {}", name, err))
+                        }
+                    };
+                }
+            }
+        }
+        if self.modified() && !bang {
+            return Err("No write since last change (add ! to override)".to_string());
+        }
+        let row = self.file.row_pos;
+        let message = self
+            .open_file(&name, encoding)
+            .map_err(|err| format!("Cannot read \"{}\": {}", name, err))?;
+        self.file.row_pos = row.min(self.file.lines.len() - 1);
+        self.scroll_to_cursor();
+        Ok(Effect::Message(message))
+    }
+
+    // `:sp [file]` and `:vs [file]`, which edit `file` in the new window.
+    fn split_command(&mut self, split: Split, args: &str) -> Result<Effect, String> {
+        self.split_window(split)?;
+        match args {
+            "" => Ok(Effect::Redraw),
+            args => self.edit_command(false, args),
+        }
+    }
+
+    // `:new` and `:vnew`, which start a new empty buffer in the new window.
+    fn new_command(&mut self, split: Split) -> Result<Effect, String> {
+        self.split_window(split)?;
+        self.new_buffer();
+        Ok(Effect::Redraw)
+    }
+
+    // `:tabnew [file]` and `:tabedit [file]`, which edit `file`, or a new
+    // empty buffer, in a new tab page.
+    fn tab_command(&mut self, args: &str) -> Result<Effect, String> {
+        self.new_tab();
+        match args {
+            "" => {
+                self.new_buffer();
+                Ok(Effect::Redraw)
+            }
+            args => self.edit_command(false, args),
+        }
+    }
+
+    // `:ls`, one entry for each buffer with its number, `%` for the current
+    // buffer, `#` for the alternate one and `+` if it was modified.
+    fn list_buffers(&self) -> String {
+        let entries: Vec<_> = self
+            .buffer_numbers()
+            .into_iter()
+            .map(|number| {
+                let file = self.buffer(number);
+                let current = match Some(number) {
+                    n if n == Some(self.file.number) => '%',
+                    n if n == self.alternate => '#',
+                    _ => ' ',
+                };
+                let modified = if self.buffer_modified(file) { '+' } else { ' ' };
+                format!(
+                    "{} {}{} \"{}\" line {}",
+                    number,
+                    current,
+                    modified,
+                    file.display_name(),
+                    file.row_pos + 1
+                )
+            })
+            .collect();
+        entries.join(" | ")
+    }
+
+    // The buffer that `arg` of `:b` and `:bd` stands for: its number, its
+    // name or a part of the name that only one buffer has.
+    fn find_buffer(&self, arg: &str) -> Result<usize, String> {
+        let files = || iter::once(&self.file).chain(&self.buffers);
+        if let Ok(number) = arg.parse::<usize>() {
+            return match files().any(|file| file.number == number) {
+                true => Ok(number),
+                false => Err(format!("Buffer {} does not exist", number)),
+            };
+        }
+        if let Some(file) = files().find(|file| file.name == arg) {
+            return Ok(file.number);
+        }
+        let matches: Vec<_> = files().filter(|file| file.name.contains(arg)).collect();
+        match matches[..] {
+            [file] => Ok(file.number),
+            [] => Err(format!("No matching buffer for {}", arg)),
+            _ => Err(format!("More than one match for {}", arg)),
+        }
+    }
+
+    // `:bnext` and `:bprevious`, which wrap around at the ends of the list.
+    fn cycle_buffer(&mut self, forward: bool) -> Result<Effect, String> {
+        let numbers = self.buffer_numbers();
+        let index = numbers.binary_search(&self.file.number).unwrap();
+        let index = match forward {
+            true => (index + 1) % numbers.len(),
+            false => (index + numbers.len() - 1) % numbers.len(),
+        };
+        self.switch_buffer(numbers[index])?;
+        Ok(Effect::Redraw)
+    }
+
+    // `:bd[elete][!] [N]`, which deletes the current buffer without `N`.
+    fn delete_command(&mut self, bang: bool, args: &str) -> Result<Effect, String> {
+        let number = match args {
+            "" => self.file.number,
+            arg => self.find_buffer(arg)?,
+        };
+        if !bang && self.buffer_modified(self.buffer(number)) {
+            return Err(format!(
+                "No write since last change for buffer {} (add ! to override)",
+                number
+            ));
+        }
+        self.delete_buffer(number);
+        Ok(Effect::Redraw)
+    }
+
+    pub(crate) fn quit_command(&self, bang: bool) -> Result<Effect, String> {
+        if bang {
+            return Ok(Effect::Quit);
+        }
+        if self.modified() {
+            return Err("No write since last change (add ! to override)".to_string());
+        }
+        self.check_hidden().map(|()| Effect::Quit)
+    }
+
+    // Quitting loses the changes in the buffers that are not current too.
+    pub(crate) fn check_hidden(&self) -> Result<(), String> {
+        match self.buffers.iter().find(|file| self.buffer_modified(file)) {
+            Some(file) => Err(format!(
+                "No write since last change for buffer \"{}\"",
+                file.display_name()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // Applies a single argument of `:set`, returning the text to show for
+    // `:set name?` and options that are not flags.
+    fn set_arg(&mut self, arg: &str, local: bool) -> Result<Option<String>, String> {
+        let (def, value) = match options::parse_arg(arg)? {
+            SetArg::Show(def) => return Ok(Some(options::show(def, self.option(def)))),
+            SetArg::Enable(def) => (def, OptionValue::Bool(true)),
+            SetArg::Disable(def) => match self.option(def) {
+                OptionValue::Bool(_) => (def, OptionValue::Bool(false)),
+                _ => return Err(format!("Invalid argument: {}", arg)),
+            },
+            SetArg::Toggle(def) => match self.option(def) {
+                OptionValue::Bool(b) => (def, OptionValue::Bool(!b)),
+                _ => return Err(format!("Invalid argument: {}", arg)),
+            },
+            SetArg::Reset(def) => (def, def.default_value()),
+            SetArg::Modify(def, op, text) => {
+                let value = options::combine(def, self.option(def), op, def.parse(&text)?)?;
+                def.check(&value)?;
+                (def, value)
+            }
+        };
+        self.set_option(def, value, local);
+        if def.name == "filetype" {
+            self.filetype_changed()?;
+        }
+        Ok(None)
+    }
+
+    // `:set` and `:setlocal`. Without arguments, shows the options that differ
+    // from their defaults, or all of them with `:set all`.
+    pub fn set_command(&mut self, args: &str, local: bool) -> Result<Effect, String> {
+        if args.is_empty() || args == "all" {
+            let shown: Vec<_> = options::OPTIONS
+                .iter()
+                .filter(|def| args == "all" || *self.option(def) != def.default_value())
+                .map(|def| options::show(def, self.option(def)))
+                .collect();
+            return Ok(Effect::Message(shown.join("  ")));
+        }
+        let mut shown = Vec::new();
+        for arg in options::split_args(args) {
+            if let Some(text) = self.set_arg(&arg, local)? {
+                shown.push(text);
+            }
+        }
+        match shown.is_empty() {
+            true => Ok(Effect::Redraw),
+            false => Ok(Effect::Message(shown.join("  "))),
+        }
+    }
+
+    pub fn source(&mut self, path: Option<PathBuf>) -> Result<Effect, String> {
+        let path = path
+            .or_else(|| self.config_dir.as_ref().map(|dir| dir.join("config.toml")))
+            .ok_or("No config file to source")?;
+        let errors = match config::load(&path) {
+            Ok(config) => self.apply_config(config),
+            Err(err) => vec![err.to_string()],
+        };
+        match errors.is_empty() {
+            true => Ok(Effect::Redraw),
+            false => Err(errors.join("; ")),
+        }
+    }
+
+    // `:map`, `:noremap`, `:unmap`, `:mapclear` and their mode-specific
+    // variants.
+    pub fn map_command(
+        &mut self,
+        command: MapCommand,
+        modes: &str,
+        args: &str,
+    ) -> Result<Effect, String> {
+        let (lhs, rhs) = match args.split_once(char::is_whitespace) {
+            Some((lhs, rhs)) => (lhs, rhs.trim()),
+            None => (args, ""),
+        };
+        match command {
+            MapCommand::Map { .. } if rhs.is_empty() => {
+                let prefix = match lhs.is_empty() {
+                    true => Vec::new(),
+                    false => self.parse_keys(lhs)?,
+                };
+                let lines = self.keymaps.list(modes, &prefix);
+                Ok(Effect::Message(match lines.is_empty() {
+                    true => "No mapping found".to_string(),
+                    false => lines.join(" | "),
+                }))
+            }
+            MapCommand::Map { noremap } => {
+                self.define_mapping(modes, lhs, rhs, noremap)?;
+                Ok(Effect::None)
+            }
+            MapCommand::Unmap if lhs.is_empty() => Err("Argument required".to_string()),
+            MapCommand::Unmap => {
+                let keys = self.parse_keys(lhs)?;
+                match self.keymaps.unmap(modes, &keys) {
+                    true => Ok(Effect::None),
+                    false => Err(format!("No such mapping: {}", lhs)),
+                }
+            }
+            MapCommand::Clear => {
+                self.keymaps.clear(modes);
+                Ok(Effect::None)
+            }
+        }
+    }
+}
+
+// The name of a `:map` family command as `keymap::map_command` takes it.
+fn map_name(name: &str, bang: bool) -> String {
+    match bang {
+        true => format!("{}!", name),
+        false => name.to_string(),
+    }
+}
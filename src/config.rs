@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+use toml::{Table, Value};
+
+// `$XDG_CONFIG_HOME/viprust`, falling back to `~/.config/viprust`.
+pub fn config_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("viprust")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/viprust")),
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+// A value from the config file together with where it was written, so that
+// errors found while applying it can point back at it.
+pub struct Setting {
+    pub name: String,
+    pub value: Value,
+    pub line: usize,
+    pub column: usize,
+}
+
+pub struct KeyRemap {
+    pub mode: char,
+    pub lhs: String,
+    pub rhs: String,
+    pub noremap: bool,
+    pub line: usize,
+    pub column: usize,
+}
+
+// The contents of `config.toml`:
+//
+//     theme = "gruvbox"
+//
+//     [options]
+//     shiftwidth = 4
+//
+//     [keys.normal]
+//     H = "0"
+//     "<leader>w" = { keys = ":w<CR>", remap = true }
+//
+//     [filetype.markdown]
+//     shiftwidth = 2
+#[derive(Default)]
+pub struct Config {
+    pub path: PathBuf,
+    pub theme: Option<String>,
+    pub options: Vec<Setting>,
+    pub keys: Vec<KeyRemap>,
+    pub filetypes: HashMap<String, Vec<Setting>>,
+}
+
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+impl Config {
+    pub fn error(&self, line: usize, column: usize, message: String) -> ConfigError {
+        ConfigError {
+            path: self.path.clone(),
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+// 1-based line and column of a byte offset.
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+// Finds where `key` is defined inside the table with the header `section`,
+// or at the top level if `section` is empty. This is only used to point error
+// messages at the right line, so it is fine for it to be approximate.
+fn locate(source: &str, section: &str, key: &str) -> (usize, usize) {
+    let mut in_section = section.is_empty();
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') {
+            let header = trimmed.trim_end().trim_matches(['[', ']']).trim();
+            in_section = header == section;
+        } else if in_section {
+            let name = trimmed
+                .split('=')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .trim_matches(['"', '\'']);
+            if name == key {
+                return position(source, offset + line.len() - trimmed.len());
+            }
+        }
+        offset += line.len();
+    }
+    (1, 1)
+}
+
+fn settings(source: &str, section: &str, table: &Table) -> Vec<Setting> {
+    table
+        .iter()
+        .map(|(name, value)| {
+            let (line, column) = locate(source, section, name);
+            Setting {
+                name: name.clone(),
+                value: value.clone(),
+                line,
+                column,
+            }
+        })
+        .collect()
+}
+
+fn mode_for(section: &str) -> Option<char> {
+    Some(match section {
+        "normal" => 'n',
+        "visual" => 'v',
+        "operator" => 'o',
+        "insert" => 'i',
+        "command" => 'c',
+        _ => return None,
+    })
+}
+
+pub fn parse(path: &Path, source: &str) -> Result<Config, ConfigError> {
+    let error = |line, column, message: String| ConfigError {
+        path: path.to_path_buf(),
+        line,
+        column,
+        message,
+    };
+    let table: Table = source.parse().map_err(|err: toml::de::Error| {
+        let (line, column) = err
+            .span()
+            .map_or((1, 1), |span| position(source, span.start));
+        let message = err.message().lines().collect::<Vec<_>>().join(", ");
+        error(line, column, message)
+    })?;
+
+    let mut config = Config {
+        path: path.to_path_buf(),
+        ..Config::default()
+    };
+    for (key, value) in &table {
+        let (line, column) = locate(source, "", key);
+        let expect_table = |value: &Value| {
+            value
+                .as_table()
+                .cloned()
+                .ok_or_else(|| error(line, column, format!("`{}` must be a table", key)))
+        };
+        match key.as_str() {
+            "theme" => match value {
+                Value::String(name) => config.theme = Some(name.clone()),
+                _ => return Err(error(line, column, "`theme` must be a string".into())),
+            },
+            "options" => config.options = settings(source, "options", &expect_table(value)?),
+            "keys" => {
+                for (section, remaps) in expect_table(value)? {
+                    let header = format!("keys.{}", section);
+                    let (line, column) = locate(source, "keys", &section);
+                    let mode = mode_for(&section).ok_or_else(|| {
+                        error(line, column, format!("unknown mode `{}`", section))
+                    })?;
+                    let remaps = remaps.as_table().ok_or_else(|| {
+                        error(line, column, format!("`{}` must be a table", header))
+                    })?;
+                    for (lhs, rhs) in remaps {
+                        let (line, column) = locate(source, &header, lhs);
+                        // Keys from the config file are not remapped again
+                        // unless asked for with `remap = true`.
+                        let (rhs, remap) = match rhs {
+                            Value::String(rhs) => {
+                                (Some(rhs.as_str()), Some(&Value::Boolean(false)))
+                            }
+                            Value::Table(table) => (
+                                table.get("keys").and_then(Value::as_str),
+                                table.get("remap").or(Some(&Value::Boolean(false))),
+                            ),
+                            _ => (None, None),
+                        };
+                        let (Some(rhs), Some(Value::Boolean(remap))) = (rhs, remap) else {
+                            let message = format!(
+                                "`{}` must map to a string or a table with `keys` and `remap`",
+                                lhs
+                            );
+                            return Err(error(line, column, message));
+                        };
+                        config.keys.push(KeyRemap {
+                            mode,
+                            lhs: lhs.clone(),
+                            rhs: rhs.to_string(),
+                            noremap: !remap,
+                            line,
+                            column,
+                        });
+                    }
+                }
+            }
+            "filetype" => {
+                for (filetype, overrides) in expect_table(value)? {
+                    let header = format!("filetype.{}", filetype);
+                    let overrides = overrides.as_table().ok_or_else(|| {
+                        error(line, column, format!("`{}` must be a table", header))
+                    })?;
+                    let overrides = settings(source, &header, overrides);
+                    config.filetypes.insert(filetype, overrides);
+                }
+            }
+            _ => return Err(error(line, column, format!("unknown key `{}`", key))),
+        }
+    }
+    Ok(config)
+}
+
+// Loads the config file at `path`. A missing file is the same as an empty one.
+pub fn load(path: &Path) -> Result<Config, ConfigError> {
+    match fs::read_to_string(path) {
+        Ok(source) => parse(path, &source),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config {
+            path: path.to_path_buf(),
+            ..Config::default()
+        }),
+        Err(err) => Err(ConfigError {
+            path: path.to_path_buf(),
+            line: 1,
+            column: 1,
+            message: err.to_string(),
+        }),
+    }
+}
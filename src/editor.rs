@@ -0,0 +1,1362 @@
+use crate::buffer::{self, EditorFile, FileFormat, Format, Line};
+use crate::config::{Config, Setting};
+use crate::encoding::{self, Encoding};
+use crate::highlight::{self, Highlighter, Syntax};
+use crate::indent::{self, IndentOptions};
+use crate::key::{self, Key};
+use crate::keymap::{self, Keymaps, Lookup, Mapping, Trie};
+use crate::options::{self, CursorShape, OptionDef, OptionValue, Options, Scope};
+use crate::screen;
+use crate::tab::TabPage;
+use crate::terminal::Backend;
+use crate::theme::{self, Theme};
+use crate::window::{Layout, Window};
+use crossterm::event::{Event, KeyCode};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::iter;
+use std::mem;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(PartialEq, Debug)]
+pub enum EditorMode {
+    Normal,
+    Insert,
+    Command,
+}
+
+#[derive(Clone)]
+pub enum EditorAction {
+    MoveLeft,
+    MoveDown,
+    MoveRight,
+    MoveUp,
+    MoveToStartOfLine,
+    MoveToEndOfLine,
+    Save,
+    InsertMode,
+    Append,
+    CommandMode,
+    CommandChar(char),
+    CommandEnter,
+    Quit,
+    NormalMode,
+    Backspace,
+    SplitLine,
+    InsertChar(char),
+    Reindent(usize, usize),
+    Operator(char),
+    ScrollDown(ScrollAmount),
+    ScrollUp(ScrollAmount),
+    ScrollCursorTo(ScreenLine),
+    AlternateBuffer,
+    // A `Ctrl-W` command.
+    Window(char),
+    NextTab,
+    PreviousTab,
+    // `gj`, `gk`, `g0` and `g$`, which move by rows of the screen when lines
+    // wrap.
+    MoveDisplayDown,
+    MoveDisplayUp,
+    MoveToStartOfDisplayLine,
+    MoveToEndOfDisplayLine,
+    NoOp,
+}
+
+#[derive(Clone, Copy)]
+pub enum ScrollAmount {
+    Line,
+    HalfPage,
+    Page,
+}
+
+// What is left to do after `Editor::apply` carried out an action.
+#[derive(PartialEq, Debug)]
+pub enum Effect {
+    None,
+    // The state of the editor changed and the screen has to be drawn again.
+    Redraw,
+    Quit,
+    // A message to show in the status bar.
+    Message(String),
+    Write(WriteRequest),
+}
+
+// Lines of the file to be written by the caller of `Editor::apply`.
+#[derive(PartialEq, Debug)]
+pub struct WriteRequest {
+    pub path: PathBuf,
+    pub lines: Range<usize>,
+    pub append: bool,
+    // Whether to quit once the lines were written.
+    pub quit: bool,
+    // Whether a read-only file is replaced, as with `:w!`.
+    pub force: bool,
+    // Whether the buffer takes the name of the file once it was written, as
+    // with `:saveas`.
+    pub rename: bool,
+}
+
+// Where `zt`, `zz` and `zb` put the line of the cursor.
+#[derive(Clone, Copy)]
+pub enum ScreenLine {
+    Top,
+    Middle,
+    Bottom,
+}
+
+// The built-in commands of normal, insert and command-line mode. Keys that
+// are not bound in insert and command-line mode are typed as they are.
+fn builtin_bindings() -> HashMap<char, Trie<EditorAction>> {
+    let normal = [
+        ("k", EditorAction::MoveUp),
+        ("<Up>", EditorAction::MoveUp),
+        ("j", EditorAction::MoveDown),
+        ("<Down>", EditorAction::MoveDown),
+        ("l", EditorAction::MoveRight),
+        ("<Right>", EditorAction::MoveRight),
+        ("h", EditorAction::MoveLeft),
+        ("<Left>", EditorAction::MoveLeft),
+        ("i", EditorAction::InsertMode),
+        (":", EditorAction::CommandMode),
+        ("q", EditorAction::Quit),
+        ("s", EditorAction::Save),
+        ("0", EditorAction::MoveToStartOfLine),
+        ("$", EditorAction::MoveToEndOfLine),
+        ("gj", EditorAction::MoveDisplayDown),
+        ("g<Down>", EditorAction::MoveDisplayDown),
+        ("gk", EditorAction::MoveDisplayUp),
+        ("g<Up>", EditorAction::MoveDisplayUp),
+        ("g0", EditorAction::MoveToStartOfDisplayLine),
+        ("g<Home>", EditorAction::MoveToStartOfDisplayLine),
+        ("g$", EditorAction::MoveToEndOfDisplayLine),
+        ("g<End>", EditorAction::MoveToEndOfDisplayLine),
+        ("a", EditorAction::Append),
+        ("=", EditorAction::Operator('=')),
+        ("<C-e>", EditorAction::ScrollDown(ScrollAmount::Line)),
+        ("<C-y>", EditorAction::ScrollUp(ScrollAmount::Line)),
+        ("<C-d>", EditorAction::ScrollDown(ScrollAmount::HalfPage)),
+        ("<C-u>", EditorAction::ScrollUp(ScrollAmount::HalfPage)),
+        ("<C-f>", EditorAction::ScrollDown(ScrollAmount::Page)),
+        ("<PageDown>", EditorAction::ScrollDown(ScrollAmount::Page)),
+        ("<C-b>", EditorAction::ScrollUp(ScrollAmount::Page)),
+        ("<PageUp>", EditorAction::ScrollUp(ScrollAmount::Page)),
+        ("zt", EditorAction::ScrollCursorTo(ScreenLine::Top)),
+        ("zz", EditorAction::ScrollCursorTo(ScreenLine::Middle)),
+        ("zb", EditorAction::ScrollCursorTo(ScreenLine::Bottom)),
+        // Terminals send `Ctrl-^` as `Ctrl-6`.
+        ("<C-^>", EditorAction::AlternateBuffer),
+        ("<C-6>", EditorAction::AlternateBuffer),
+        ("<C-w>s", EditorAction::Window('s')),
+        ("<C-w>v", EditorAction::Window('v')),
+        ("<C-w>h", EditorAction::Window('h')),
+        ("<C-w>j", EditorAction::Window('j')),
+        ("<C-w>k", EditorAction::Window('k')),
+        ("<C-w>l", EditorAction::Window('l')),
+        ("<C-w>w", EditorAction::Window('w')),
+        ("<C-w><C-w>", EditorAction::Window('w')),
+        ("<C-w>W", EditorAction::Window('W')),
+        ("<C-w>c", EditorAction::Window('c')),
+        ("<C-w>q", EditorAction::Window('q')),
+        ("<C-w>o", EditorAction::Window('o')),
+        ("<C-w>=", EditorAction::Window('=')),
+        ("<C-w>+", EditorAction::Window('+')),
+        ("<C-w>-", EditorAction::Window('-')),
+        ("<C-w><", EditorAction::Window('<')),
+        ("<C-w>>", EditorAction::Window('>')),
+        ("gt", EditorAction::NextTab),
+        ("<C-PageDown>", EditorAction::NextTab),
+        ("gT", EditorAction::PreviousTab),
+        ("<C-PageUp>", EditorAction::PreviousTab),
+    ];
+    let insert = [
+        ("<Esc>", EditorAction::NormalMode),
+        ("<BS>", EditorAction::Backspace),
+        ("<CR>", EditorAction::SplitLine),
+    ];
+    let command = [
+        ("<Esc>", EditorAction::NormalMode),
+        ("<CR>", EditorAction::CommandEnter),
+    ];
+    let trie = |bindings: &[(&str, EditorAction)]| {
+        let mut trie = Trie::default();
+        for (keys, action) in bindings {
+            trie.insert(&key::parse_keys(keys).unwrap(), action.clone());
+        }
+        trie
+    };
+    HashMap::from([
+        ('n', trie(&normal)),
+        ('i', trie(&insert)),
+        ('c', trie(&command)),
+    ])
+}
+
+pub struct Editor {
+    pub mode: EditorMode,
+    // The current buffer, and the other buffers in the order of their
+    // numbers.
+    pub file: EditorFile,
+    pub buffers: Vec<EditorFile>,
+    // The number of the buffer that was current before this one, which
+    // `Ctrl-^` goes back to.
+    pub alternate: Option<usize>,
+    pub(crate) last_number: usize,
+    pub syntaxes: Vec<Rc<Syntax>>,
+    pub theme: Theme,
+    pub config: Config,
+    // Where themes, syntaxes and the config file are looked for, which is
+    // None with `--clean`.
+    pub config_dir: Option<PathBuf>,
+    pub(crate) bindings: HashMap<char, Trie<EditorAction>>,
+    pub keymaps: Keymaps,
+    // Events that are yet to be processed, and whether they may be remapped.
+    pub(crate) input: VecDeque<(Event, bool)>,
+    // Keys that may be the start of a mapping, waiting for the next key or
+    // `timeoutlen` to decide.
+    pub(crate) typed: Vec<Key>,
+    // How many mappings were expanded since a key was last typed, to stop
+    // recursive mappings.
+    pub(crate) map_depth: usize,
+    // The keys of a built-in command typed so far.
+    pub(crate) keys: Vec<Key>,
+    pub message: Option<String>,
+    pub command: String,
+    pub(crate) pending: Option<char>,
+    // The other windows, and the id of the current one, which keeps its
+    // cursor in `file` and its options in `window_options`.
+    pub windows: Vec<Window>,
+    pub window: usize,
+    pub layout: Layout,
+    pub(crate) last_window: usize,
+    // The other tab pages, and the place of the current one among them.
+    pub tabs: Vec<TabPage>,
+    pub tab: usize,
+    // The size of the current window without its status line, and of the
+    // terminal.
+    pub num_rows: usize,
+    pub num_cols: usize,
+    pub screen_rows: usize,
+    pub screen_cols: usize,
+    pub options: Options,
+    pub window_options: Options,
+}
+
+impl Editor {
+    // An editor with an empty buffer that has no name yet, for a terminal of
+    // `cols` by `rows`.
+    pub fn new(
+        syntaxes: Vec<Rc<Syntax>>,
+        theme: Theme,
+        config: Config,
+        config_dir: Option<PathBuf>,
+        (cols, rows): (usize, usize),
+    ) -> Editor {
+        Editor {
+            mode: EditorMode::Normal,
+            file: EditorFile::new(1),
+            buffers: Vec::new(),
+            alternate: None,
+            last_number: 1,
+            syntaxes,
+            theme,
+            config,
+            config_dir,
+            bindings: builtin_bindings(),
+            keymaps: Keymaps::default(),
+            input: VecDeque::new(),
+            typed: Vec::new(),
+            map_depth: 0,
+            keys: Vec::new(),
+            message: None,
+            command: "".to_string(),
+            pending: None,
+            windows: Vec::new(),
+            window: 1,
+            layout: Layout::Window(1),
+            last_window: 1,
+            tabs: Vec::new(),
+            tab: 0,
+            num_rows: rows.saturating_sub(1).max(1),
+            num_cols: cols,
+            screen_rows: rows,
+            screen_cols: cols,
+            options: Options::defaults(),
+            window_options: Options::default(),
+        }
+    }
+
+    // An editor with only the built-in syntaxes and themes and no config,
+    // which never reads the config directory.
+    pub fn builtin(window_size: (usize, usize)) -> Editor {
+        let (syntaxes, _) = highlight::load_syntaxes(None);
+        let theme = theme::builtin("default").unwrap();
+        Editor::new(syntaxes, theme, Config::default(), None, window_size)
+    }
+
+    // Reads a file and makes it the current file, returning the message that
+    // tells what was read. A file that does not exist yet starts out empty
+    // and is created when it is written.
+    pub fn open_file(&mut self, filename: &str, encoding: Option<Encoding>) -> io::Result<String> {
+        let bytes = match fs::read(filename) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.load_text(filename, "");
+                return Ok(format!("\"{}\" [New]", filename));
+            }
+            Err(err) => return Err(err),
+        };
+        self.load_bytes(filename, &bytes, encoding)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(format!(
+            "\"{}\" {}{}L, {}B",
+            filename,
+            buffer::format_tags(self.format()),
+            self.file.line_count(),
+            bytes.len()
+        ))
+    }
+
+    // Decodes the contents of a file and makes it the current file. The
+    // encoding is detected unless one is given.
+    pub fn load_bytes(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+        encoding: Option<Encoding>,
+    ) -> Result<(), String> {
+        let (text, encoding, bom) = encoding::decode(bytes, encoding)?;
+        self.load_text(name, &text);
+        let format = Format {
+            encoding,
+            bom,
+            ..self.format()
+        };
+        self.set_format(format);
+        self.file.saved_format = format;
+        Ok(())
+    }
+
+    // Makes the contents of a file the current file, keeping the format it
+    // is in.
+    pub fn load_text(&mut self, name: &str, text: &str) {
+        let (lines, format) = buffer::split_lines(text);
+        self.load_file(name, lines);
+        self.file.empty = text.is_empty();
+        self.set_format(format);
+        self.file.saved_format = format;
+    }
+
+    pub fn format(&self) -> Format {
+        self.buffer_format(&self.file)
+    }
+
+    // The format of `file`, which does not have to be the current buffer.
+    pub fn buffer_format(&self, file: &EditorFile) -> Format {
+        let string = |name| match self.buffer_option(file, name) {
+            OptionValue::String(text) => text.as_str(),
+            _ => "",
+        };
+        Format {
+            encoding: Encoding::parse(string("fileencoding")).unwrap_or(Encoding::Utf8),
+            bom: self.buffer_flag(file, "bomb"),
+            fileformat: FileFormat::parse(string("fileformat")).unwrap_or(FileFormat::Unix),
+            eol: self.buffer_flag(file, "endofline"),
+        }
+    }
+
+    pub(crate) fn set_format(&mut self, format: Format) {
+        let encoding = OptionValue::String(format.encoding.name().to_string());
+        self.set_option(options::find("fileencoding").unwrap(), encoding, true);
+        let bom = OptionValue::Bool(format.bom);
+        self.set_option(options::find("bomb").unwrap(), bom, true);
+        let fileformat = OptionValue::String(format.fileformat.name().to_string());
+        self.set_option(options::find("fileformat").unwrap(), fileformat, true);
+        let eol = OptionValue::Bool(format.eol);
+        self.set_option(options::find("endofline").unwrap(), eol, true);
+    }
+
+    pub fn modified(&self) -> bool {
+        self.buffer_modified(&self.file)
+    }
+
+    // Whether writing `file` would change it: the lines were edited or its
+    // format was changed since it was last read or written. Like in vim, a
+    // missing newline at the end only counts without `fixendofline`.
+    pub fn buffer_modified(&self, file: &EditorFile) -> bool {
+        let (format, saved) = (self.buffer_format(file), file.saved_format);
+        file.seq_cur != file.saved_seq
+            || format.encoding != saved.encoding
+            || format.bom != saved.bom
+            || format.fileformat != saved.fileformat
+            || !self.buffer_flag(file, "fixendofline") && format.eol != saved.eol
+    }
+
+    // The format the file is written in: with `fixendofline`, the last line
+    // always ends with a separator.
+    pub fn write_format(&self) -> Format {
+        let format = self.format();
+        Format {
+            eol: format.eol || self.flag("fixendofline"),
+            ..format
+        }
+    }
+
+    // Makes `lines` the contents of the current file, which is called `name`.
+    pub fn load_file(&mut self, name: &str, lines: Vec<Line>) {
+        let mut editor_file = EditorFile {
+            name: String::from(name),
+            lines,
+            highlighter: highlight::syntax_for(&self.syntaxes, name).map(Highlighter::new),
+            ..EditorFile::new(self.file.number)
+        };
+
+        if let Some(highlighter) = &editor_file.highlighter {
+            let filetype = OptionValue::String(highlighter.name().to_string());
+            editor_file
+                .options
+                .set(options::find("filetype").unwrap(), filetype);
+        }
+        self.file = editor_file;
+        self.apply_filetype_options();
+    }
+
+    // Makes `file` the current buffer. The buffer that was current stays in
+    // the list as the alternate buffer.
+    fn enter_buffer(&mut self, file: EditorFile) {
+        self.alternate = Some(self.put_buffer(file));
+        self.scroll_to_cursor();
+    }
+
+    // Makes `file` the current buffer and puts the one that was current back
+    // in the list, returning its number.
+    pub(crate) fn put_buffer(&mut self, file: EditorFile) -> usize {
+        let previous = mem::replace(&mut self.file, file);
+        let number = previous.number;
+        let index = self.buffers.partition_point(|b| b.number < number);
+        self.buffers.insert(index, previous);
+        number
+    }
+
+    // Takes a buffer other than the current one out of the list.
+    pub(crate) fn take_buffer(&mut self, number: usize) -> Option<EditorFile> {
+        let index = self.buffers.iter().position(|b| b.number == number)?;
+        Some(self.buffers.remove(index))
+    }
+
+    // Adds an empty buffer without a name and makes it the current one.
+    pub fn new_buffer(&mut self) {
+        self.last_number += 1;
+        self.enter_buffer(EditorFile::new(self.last_number));
+    }
+
+    pub fn switch_buffer(&mut self, number: usize) -> Result<(), String> {
+        if number == self.file.number {
+            return Ok(());
+        }
+        let file = self
+            .take_buffer(number)
+            .ok_or_else(|| format!("Buffer {} does not exist", number))?;
+        self.enter_buffer(file);
+        Ok(())
+    }
+
+    // Removes a buffer from the list, closing the other windows that show
+    // it. The current buffer is replaced by the alternate one, or else the
+    // next one, or a new empty buffer if it was the only one.
+    pub fn delete_buffer(&mut self, number: usize) {
+        let windows: Vec<_> = self
+            .windows
+            .iter()
+            .filter(|window| window.buffer == number)
+            .map(|window| window.id)
+            .collect();
+        for id in windows {
+            // Closing fails only for the last window, which is this one.
+            let _ = self.close_window(id);
+        }
+        self.close_tab_windows(number);
+        if number == self.file.number {
+            let next = self
+                .alternate
+                .filter(|&n| self.buffers.iter().any(|b| b.number == n))
+                .or_else(|| {
+                    let next = self.buffers.iter().find(|b| b.number > number);
+                    next.or(self.buffers.last()).map(|b| b.number)
+                });
+            match next {
+                Some(next) => self.switch_buffer(next).unwrap(),
+                None => self.new_buffer(),
+            }
+        }
+        self.buffers.retain(|b| b.number != number);
+        if self.alternate == Some(number) {
+            self.alternate = None;
+        }
+    }
+
+    pub(crate) fn buffer(&self, number: usize) -> &EditorFile {
+        iter::once(&self.file)
+            .chain(&self.buffers)
+            .find(|file| file.number == number)
+            .expect("no buffer with that number")
+    }
+
+    pub(crate) fn buffer_mut(&mut self, number: usize) -> &mut EditorFile {
+        iter::once(&mut self.file)
+            .chain(&mut self.buffers)
+            .find(|file| file.number == number)
+            .expect("no buffer with that number")
+    }
+
+    // The numbers of all buffers, in order.
+    pub fn buffer_numbers(&self) -> Vec<usize> {
+        let mut numbers: Vec<_> = self.buffers.iter().map(|b| b.number).collect();
+        let index = numbers.partition_point(|&n| n < self.file.number);
+        numbers.insert(index, self.file.number);
+        numbers
+    }
+
+    // The value of an option in the current window and buffer, which is the
+    // local value if one was set and the global value otherwise.
+    pub(crate) fn option(&self, def: &OptionDef) -> &OptionValue {
+        let local = match def.scope {
+            Scope::Global => None,
+            Scope::Window => self.window_options.get(def.name),
+            Scope::Buffer => self.file.options.get(def.name),
+        };
+        local
+            .or_else(|| self.options.get(def.name))
+            .expect("option without a global value")
+    }
+
+    // The value of a buffer option of `file`, like `option` does for the
+    // current buffer.
+    pub(crate) fn buffer_option<'a>(&'a self, file: &'a EditorFile, name: &str) -> &'a OptionValue {
+        file.options
+            .get(name)
+            .or_else(|| self.options.get(name))
+            .expect("option without a global value")
+    }
+
+    // The value of a window option in `options`, the local options of a
+    // window.
+    pub(crate) fn window_option<'a>(&'a self, options: &'a Options, name: &str) -> &'a OptionValue {
+        options
+            .get(name)
+            .or_else(|| self.options.get(name))
+            .expect("option without a global value")
+    }
+
+    fn buffer_flag(&self, file: &EditorFile, name: &str) -> bool {
+        matches!(self.buffer_option(file, name), OptionValue::Bool(true))
+    }
+
+    pub fn option_value(&self, name: &str) -> &OptionValue {
+        self.option(options::find(name).expect("unknown option"))
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        matches!(self.option_value(name), OptionValue::Bool(true))
+    }
+
+    pub fn number(&self, name: &str) -> usize {
+        match self.option_value(name) {
+            OptionValue::Number(n) => *n as usize,
+            _ => 0,
+        }
+    }
+
+    pub fn string(&self, name: &str) -> &str {
+        match self.option_value(name) {
+            OptionValue::String(text) => text,
+            _ => "",
+        }
+    }
+
+    pub fn list(&self, name: &str) -> &[String] {
+        match self.option_value(name) {
+            OptionValue::List(items) => items,
+            _ => &[],
+        }
+    }
+
+    // Sets an option both globally and locally, like `:set`, or only for the
+    // current window or buffer, like `:setlocal`. Global options can only be
+    // set globally.
+    pub fn set_option(&mut self, def: &'static OptionDef, value: OptionValue, local: bool) {
+        let local_options = match def.scope {
+            Scope::Global => None,
+            Scope::Window => Some(&mut self.window_options),
+            Scope::Buffer => Some(&mut self.file.options),
+        };
+        match local_options {
+            Some(local_options) if local => local_options.set(def, value),
+            Some(local_options) => {
+                local_options.set(def, value.clone());
+                self.options.set(def, value);
+            }
+            None => self.options.set(def, value),
+        }
+    }
+
+    // Sets `filetype` again for the name of the current buffer, as when the
+    // name changed. It is kept if no syntax matches the new name.
+    pub(crate) fn detect_filetype(&mut self) {
+        if let Some(syntax) = highlight::syntax_for(&self.syntaxes, &self.file.name) {
+            let filetype = OptionValue::String(syntax.name.clone());
+            self.file
+                .options
+                .set(options::find("filetype").unwrap(), filetype);
+            let _ = self.filetype_changed();
+        }
+    }
+
+    // Picks the highlighter and local options for a filetype set with `:set`.
+    pub(crate) fn filetype_changed(&mut self) -> Result<(), String> {
+        let filetype = self.file.filetype().unwrap_or_default();
+        self.file.highlighter = self
+            .syntaxes
+            .iter()
+            .find(|syntax| syntax.name == filetype)
+            .cloned()
+            .map(Highlighter::new);
+        let errors = self.apply_filetype_options();
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors.join("; ")),
+        }
+    }
+
+    // Sets the options of the config file, or the local ones of a filetype.
+    pub(crate) fn apply_settings(&mut self, settings: &[Setting], local: bool) -> Vec<String> {
+        let mut errors = Vec::new();
+        for setting in settings {
+            let value = options::find(&setting.name)
+                .ok_or_else(|| format!("unknown option `{}`", setting.name))
+                .and_then(|def| Ok((def, def.parse_toml(&setting.value)?)));
+            match value {
+                Ok((def, value)) => self.set_option(def, value, local),
+                Err(err) => errors.push(
+                    self.config
+                        .error(setting.line, setting.column, err)
+                        .to_string(),
+                ),
+            }
+        }
+        errors
+    }
+
+    // Applies the options, key remaps and theme of `config`, returning the
+    // errors of any that could not be applied.
+    pub fn apply_config(&mut self, config: Config) -> Vec<String> {
+        self.config = config;
+        let options = mem::take(&mut self.config.options);
+        let mut errors = self.apply_settings(&options, false);
+        self.config.options = options;
+
+        let keys = mem::take(&mut self.config.keys);
+        for remap in &keys {
+            let modes = remap.mode.to_string();
+            if let Err(err) = self.define_mapping(&modes, &remap.lhs, &remap.rhs, remap.noremap) {
+                let err = format!("{}: {}", remap.lhs, err);
+                errors.push(self.config.error(remap.line, remap.column, err).to_string());
+            }
+        }
+        self.config.keys = keys;
+
+        if let Some(name) = &self.config.theme {
+            match theme::load(name, self.config_dir.as_deref()) {
+                Ok(theme) => self.theme = theme,
+                Err(err) => errors.push(format!("{}: {}", self.config.path.display(), err)),
+            }
+        }
+        errors.extend(self.apply_filetype_options());
+        errors
+    }
+
+    // Applies the config that the editor was created with, which is only
+    // done after the commands of `--cmd` ran.
+    pub fn apply_startup_config(&mut self) -> Vec<String> {
+        let config = mem::take(&mut self.config);
+        self.apply_config(config)
+    }
+
+    fn apply_filetype_options(&mut self) -> Vec<String> {
+        if let Some(indenter) = self.file.filetype().and_then(indent::indenter_for) {
+            for (name, value) in indenter.options() {
+                self.set_option(options::find(name).unwrap(), value, true);
+            }
+        }
+        let overrides = self
+            .file
+            .filetype()
+            .and_then(|filetype| self.config.filetypes.remove(filetype));
+        let Some(overrides) = overrides else {
+            return Vec::new();
+        };
+        let errors = self.apply_settings(&overrides, true);
+        let filetype = self.file.filetype().unwrap().to_string();
+        self.config.filetypes.insert(filetype, overrides);
+        errors
+    }
+
+    pub(crate) fn parse_keys(&self, keys: &str) -> Result<Vec<Key>, String> {
+        let leader = match self.option_value("mapleader") {
+            OptionValue::String(leader) => leader.as_str(),
+            _ => "\\",
+        };
+        key::parse_keys(&keymap::expand_leader(keys, leader))
+    }
+
+    pub fn define_mapping(
+        &mut self,
+        modes: &str,
+        lhs: &str,
+        rhs: &str,
+        noremap: bool,
+    ) -> Result<(), String> {
+        let mapping = Mapping {
+            lhs: self.parse_keys(lhs)?,
+            keys: self.parse_keys(rhs)?,
+            noremap,
+        };
+        self.keymaps.map(modes, &mapping);
+        Ok(())
+    }
+
+    // The mode that mappings are looked up in.
+    fn mapping_mode(&self) -> char {
+        match self.mode {
+            EditorMode::Normal if self.pending.is_some() => 'o',
+            EditorMode::Normal => 'n',
+            EditorMode::Insert => 'i',
+            EditorMode::Command => 'c',
+        }
+    }
+
+    fn timeoutlen(&self) -> Duration {
+        Duration::from_millis(self.number("timeoutlen") as u64)
+    }
+
+    // Adds a key that may be remapped to the typed keys. Once they can no
+    // longer be the start of a longer mapping, they are replaced by what they
+    // are mapped to in `input`.
+    fn map_key(&mut self, key: Key) {
+        self.typed.push(key);
+        let waiting = self
+            .keymaps
+            .get(self.mapping_mode())
+            .map(|trie| trie.lookup(&self.typed));
+        if !matches!(waiting, Some(Lookup::Prefix | Lookup::Ambiguous(_))) {
+            self.resolve_typed();
+        }
+    }
+
+    // Stops waiting for more keys: the longest mapping that the typed keys
+    // start with is expanded, or otherwise the first key is taken as it is.
+    // The keys after that are looked up again.
+    fn resolve_typed(&mut self) {
+        let typed = mem::take(&mut self.typed);
+        if typed.is_empty() {
+            return;
+        }
+        let event = |key: Key| Event::Key(key.into());
+        let mapping = self
+            .keymaps
+            .get(self.mapping_mode())
+            .and_then(|trie| trie.longest(&typed));
+        let (used, expansion): (usize, Vec<_>) = match mapping {
+            Some((len, mapping)) => {
+                // Like in vim, a mapping whose right-hand side starts with its
+                // left-hand side does not remap its first key again.
+                let starts_with_lhs = mapping.keys.starts_with(&typed[..len]);
+                let keys = mapping.keys.iter().enumerate().map(|(i, key)| {
+                    let remap = !(mapping.noremap || i == 0 && starts_with_lhs);
+                    (event(*key), remap)
+                });
+                (len, keys.collect())
+            }
+            None => (1, vec![(event(typed[0]), false)]),
+        };
+        if mapping.is_some() {
+            self.map_depth += 1;
+            if self.map_depth > 1000 {
+                self.input.clear();
+                self.message = Some("Recursive mapping".to_string());
+                return;
+            }
+        }
+        for key in typed[used..].iter().rev() {
+            self.input.push_front((event(*key), true));
+        }
+        for input in expansion.into_iter().rev() {
+            self.input.push_front(input);
+        }
+    }
+
+    pub fn process_input(&mut self, event: Event) -> EditorAction {
+        let Event::Key(event) = event else {
+            return EditorAction::NoOp;
+        };
+        let key = Key::from(event);
+        if let Some(operator) = self.pending.take() {
+            return self.process_operator(operator, key);
+        }
+        let mode = match self.mode {
+            EditorMode::Normal => 'n',
+            EditorMode::Insert => 'i',
+            EditorMode::Command => 'c',
+        };
+        self.keys.push(key);
+        let action = match self.bindings[&mode].lookup(&self.keys) {
+            Lookup::Prefix => return EditorAction::NoOp,
+            Lookup::Exact(action) | Lookup::Ambiguous(action) => action.clone(),
+            Lookup::None => match (&self.mode, key.as_char()) {
+                (EditorMode::Insert, Some(c)) => EditorAction::InsertChar(c),
+                (EditorMode::Command, Some(c)) => EditorAction::CommandChar(c),
+                _ => EditorAction::NoOp,
+            },
+        };
+        self.keys.clear();
+        match action {
+            EditorAction::Operator(operator) => {
+                self.pending = Some(operator);
+                EditorAction::NoOp
+            }
+            action => action,
+        }
+    }
+
+    fn process_operator(&self, operator: char, key: Key) -> EditorAction {
+        let row = self.file.row_pos;
+        let last = self.file.lines.len().saturating_sub(1);
+        if !key.modifiers.is_empty() {
+            return EditorAction::NoOp;
+        }
+        let (start, end) = match key.code {
+            KeyCode::Char(c) if c == operator => (row, row),
+            KeyCode::Char('j') | KeyCode::Down => (row, (row + 1).min(last)),
+            KeyCode::Char('k') | KeyCode::Up => (row.saturating_sub(1), row),
+            KeyCode::Char('G') => (row, last),
+            _ => return EditorAction::NoOp,
+        };
+        match operator {
+            '=' => EditorAction::Reindent(start, end),
+            _ => EditorAction::NoOp,
+        }
+    }
+
+    fn indent_options(&self) -> IndentOptions {
+        IndentOptions {
+            shiftwidth: self.number("shiftwidth"),
+            tabstop: self.number("tabstop"),
+            expandtab: self.flag("expandtab"),
+        }
+    }
+
+    pub(crate) fn cursor_shape(&self) -> (CursorShape, bool) {
+        let mode = match self.mode {
+            EditorMode::Normal => "n",
+            EditorMode::Insert => "i",
+            EditorMode::Command => "c",
+        };
+        options::cursor_shape(self.list("guicursor"), mode).unwrap_or((CursorShape::Block, false))
+    }
+
+    // How many columns to scroll horizontally when the cursor leaves the
+    // screen. A `sidescroll` of 0 puts the cursor in the middle of the screen.
+    fn sidescroll(&self) -> usize {
+        match self.number("sidescroll") {
+            0 => (self.text_cols() / 2).max(1),
+            n => n.min(self.text_cols()),
+        }
+    }
+
+    // `scrolloff`, limited so that the cursor still fits between the margins.
+    pub(crate) fn scrolloff(&self) -> usize {
+        self.number("scrolloff")
+            .min(self.num_rows.saturating_sub(1) / 2)
+    }
+
+    fn sidescrolloff(&self) -> usize {
+        self.number("sidescrolloff")
+            .min(self.text_cols().saturating_sub(1) / 2)
+    }
+
+    // The width of the sign column and line numbers of the current window.
+    pub(crate) fn gutter_width(&self) -> usize {
+        let (signs, numbers) = self.gutter(&self.window_options, &self.file);
+        signs + numbers
+    }
+
+    // How many columns of text the current window shows.
+    pub(crate) fn text_cols(&self) -> usize {
+        self.num_cols.saturating_sub(self.gutter_width()).max(1)
+    }
+
+    // The display column of the cursor in its line.
+    pub(crate) fn cursor_col(&self) -> usize {
+        let line = &self.file.lines[self.file.row_pos].chars;
+        let before = line.get(..self.file.col_pos).unwrap_or(line);
+        screen::display_width(before, self.number("tabstop"))
+    }
+
+    // Keeps the cursor column within the current line after moving up or
+    // down.
+    pub fn clamp_cursor(&mut self) {
+        let line = &self.file.lines[self.file.row_pos].chars;
+        let mut col = self.file.col_pos.min(line.len());
+        while !line.is_char_boundary(col) {
+            col -= 1;
+        }
+        self.file.col_pos = col;
+    }
+
+    // Where the character before the cursor starts, and where the one under
+    // it ends. `col_pos` is a byte offset into the line.
+    fn char_before(&self) -> usize {
+        let line = &self.file.lines[self.file.row_pos].chars;
+        let col = self.file.col_pos;
+        line[..col]
+            .chars()
+            .next_back()
+            .map_or(0, |c| col - c.len_utf8())
+    }
+
+    fn char_after(&self) -> usize {
+        let line = &self.file.lines[self.file.row_pos].chars;
+        let col = self.file.col_pos;
+        line[col..]
+            .chars()
+            .next()
+            .map_or(col, |c| col + c.len_utf8())
+    }
+
+    // Scrolls the view so that the cursor is on the screen, with `scrolloff`
+    // lines above and below it and `sidescrolloff` columns to its sides.
+    // Returns true if the view moved.
+    pub fn scroll_to_cursor(&mut self) -> bool {
+        if let Some(moved) = self.scroll_wrapped() {
+            return moved;
+        }
+        let file = &self.file;
+        let row = file.row_pos;
+        let (top, left) = (file.row_scroll_pos, file.col_scroll_pos);
+        let last = file.lines.len().saturating_sub(1);
+        let so = self.scrolloff();
+        let jump = self.number("scrolljump").max(1);
+
+        let mut new_top = top;
+        if row < top + so {
+            let needed = row.saturating_sub(so);
+            new_top = needed.min(top.saturating_sub(jump));
+        } else if (row + so).min(last) >= top + self.num_rows {
+            let needed = (row + so).min(last) + 1 - self.num_rows;
+            let max_top = (last + 1).saturating_sub(self.num_rows);
+            new_top = (top + jump).min(max_top).max(needed);
+        }
+
+        let col = self.cursor_col();
+        let line_len = screen::display_width(&file.lines[row].chars, self.number("tabstop"));
+        let sso = self.sidescrolloff();
+        let step = self.sidescroll();
+        let mut new_left = left;
+        if col < left + sso {
+            let needed = col.saturating_sub(sso);
+            new_left = needed.min(left.saturating_sub(step));
+        } else if (col + sso).min(line_len) >= left + self.text_cols() {
+            let needed = (col + sso).min(line_len) + 1 - self.text_cols();
+            new_left = (left + step).max(needed).min(col);
+        }
+
+        self.file.row_scroll_pos = new_top;
+        self.file.col_scroll_pos = new_left;
+        (new_top, new_left) != (top, left)
+    }
+
+    // Moves the cursor into the screen after the view was scrolled, keeping
+    // `scrolloff` lines to the edges where possible.
+    fn cursor_into_view(&mut self) {
+        let last = self.file.lines.len().saturating_sub(1);
+        let so = self.scrolloff();
+        let top = self.file.row_scroll_pos;
+        let bottom = (top + self.visible_lines())
+            .saturating_sub(1 + so)
+            .min(last);
+        let min_row = match top {
+            0 => 0,
+            top => (top + so).min(last),
+        };
+        self.file.row_pos = self.file.row_pos.max(min_row).min(bottom.max(min_row));
+        self.clamp_cursor();
+    }
+
+    // `Ctrl-E`, `Ctrl-D` and `Ctrl-F`. The screen scrolls by half a screen
+    // for `Ctrl-D` and the cursor moves along with it.
+    pub fn scroll_down(&mut self, amount: ScrollAmount) {
+        let last = self.file.lines.len().saturating_sub(1);
+        let top = self.file.row_scroll_pos;
+        match amount {
+            ScrollAmount::Line => self.file.row_scroll_pos = (top + 1).min(last),
+            ScrollAmount::HalfPage => {
+                let half = (self.num_rows / 2).max(1);
+                let max_top = (last + 1).saturating_sub(self.num_rows);
+                self.file.row_scroll_pos = (top + half).min(max_top.max(top));
+                self.file.row_pos = (self.file.row_pos + half).min(last);
+            }
+            ScrollAmount::Page => {
+                let page = self.num_rows.saturating_sub(2).max(1);
+                self.file.row_scroll_pos = (top + page).min(last);
+            }
+        }
+        self.cursor_into_view();
+    }
+
+    // `Ctrl-Y`, `Ctrl-U` and `Ctrl-B`.
+    pub fn scroll_up(&mut self, amount: ScrollAmount) {
+        let top = self.file.row_scroll_pos;
+        match amount {
+            ScrollAmount::Line => self.file.row_scroll_pos = top.saturating_sub(1),
+            ScrollAmount::HalfPage => {
+                let half = (self.num_rows / 2).max(1);
+                self.file.row_scroll_pos = top.saturating_sub(half);
+                self.file.row_pos = self.file.row_pos.saturating_sub(half);
+            }
+            ScrollAmount::Page => {
+                let page = self.num_rows.saturating_sub(2).max(1);
+                self.file.row_scroll_pos = top.saturating_sub(page);
+            }
+        }
+        self.cursor_into_view();
+    }
+
+    // `zt`, `zz` and `zb`.
+    pub fn scroll_cursor_to(&mut self, line: ScreenLine) {
+        let row = self.file.row_pos;
+        let last = self.file.lines.len().saturating_sub(1);
+        let so = self.scrolloff();
+        self.file.row_scroll_pos = match line {
+            ScreenLine::Top => row.saturating_sub(so),
+            ScreenLine::Middle => row.saturating_sub(self.num_rows.saturating_sub(1) / 2),
+            ScreenLine::Bottom => ((row + so).min(last) + 1).saturating_sub(self.num_rows),
+        };
+    }
+
+    // Reindents the lines `start..=end` and moves the cursor to the first
+    // non-blank character of `start`.
+    pub fn reindent(&mut self, start: usize, end: usize) {
+        if let Some(indenter) = self.file.filetype().and_then(indent::indenter_for) {
+            let opts = self.indent_options();
+            indenter.reindent(&mut self.file.lines, start..end + 1, &opts);
+            self.file.edited(start, end + 1 - start, end + 1 - start);
+        }
+        self.file.row_pos = start;
+        self.file.col_pos = indent::leading_whitespace(&self.file.lines[start].chars).len();
+        self.file.col_scroll_pos = 0;
+    }
+
+    // Splits the current line at the cursor, indenting the new line according
+    // to `autoindent` and `smartindent`. Between a pair of brackets, the
+    // closing one is moved to a line of its own.
+    pub fn split_line(&mut self) {
+        let opts = self.indent_options();
+        let row = self.file.row_pos;
+        let (autoindent, smartindent) = (self.flag("autoindent"), self.flag("smartindent"));
+        let current = &mut self.file.lines[row].chars;
+        let rest = current.split_off(self.file.col_pos);
+        let base = if autoindent || smartindent {
+            indent::indent_width(current, opts.tabstop)
+        } else {
+            0
+        };
+        let rest = match autoindent || smartindent {
+            true => rest.trim_start().to_string(),
+            false => rest,
+        };
+        let opener = current
+            .trim_end()
+            .chars()
+            .last()
+            .filter(|&c| indent::is_opener(c));
+        // A line left with nothing but its indent loses it, as with vim's
+        // autoindent. Without it, the blanks are the user's to keep.
+        if (autoindent || smartindent) && current.trim().is_empty() {
+            current.clear();
+        }
+        let width = match opener {
+            Some(_) if smartindent => base + opts.shiftwidth,
+            _ => base,
+        };
+
+        let indent = indent::indent_string(width, &opts);
+        self.file.row_pos += 1;
+        self.file.col_pos = indent.len();
+
+        let closes = opener
+            .and_then(indent::matching_closer)
+            .is_some_and(|closer| smartindent && rest.starts_with(closer));
+        if closes {
+            let closer_line = indent::indent_string(base, &opts) + &rest;
+            let line = |chars| Line { chars, sign: None };
+            self.file.lines.insert(row + 1, line(indent));
+            self.file.lines.insert(row + 2, line(closer_line));
+            self.file.edited(row, 1, 3);
+        } else {
+            self.file.lines.insert(
+                row + 1,
+                Line {
+                    chars: indent + &rest,
+                    sign: None,
+                },
+            );
+            self.file.edited(row, 1, 2);
+        }
+    }
+
+    // Inserts `c` at the cursor. With `smartindent`, a closing bracket typed as
+    // the first character of a line is lined up with its opening bracket.
+    pub fn insert_char(&mut self, c: char) {
+        let row = self.file.row_pos;
+        let line = &mut self.file.lines[row].chars;
+        let at_indent = line[..self.file.col_pos].trim().is_empty();
+        line.insert(self.file.col_pos, c);
+        self.file.col_pos += c.len_utf8();
+
+        if !(self.flag("smartindent") && at_indent && indent::is_closer(c)) {
+            self.file.edited(row, 1, 1);
+            return;
+        }
+        let opts = self.indent_options();
+        let width =
+            indent::open_bracket_indent(&self.file.lines, row, self.file.col_pos - 1, opts.tabstop)
+                .unwrap_or_else(|| {
+                    indent::indent_width(&self.file.lines[row].chars, opts.tabstop)
+                        .saturating_sub(opts.shiftwidth)
+                });
+        let line = &mut self.file.lines[row].chars;
+        indent::set_indent(line, width, &opts);
+        self.file.col_pos = indent::leading_whitespace(line).len() + 1;
+        self.file.edited(row, 1, 1);
+    }
+
+    // Draws frames to `backend` and handles the events read from it, until
+    // the editor is quit.
+    pub fn run(&mut self, backend: &mut dyn Backend) -> io::Result<()> {
+        loop {
+            backend.draw(self.draw())?;
+            let (event, remap) = match self.input.pop_front() {
+                Some(input) => input,
+                // Typed keys that may be the start of a mapping are taken as they
+                // are when no key follows within `timeoutlen`.
+                None if !self.typed.is_empty() && !backend.poll(self.timeoutlen())? => {
+                    self.resolve_typed();
+                    continue;
+                }
+                None => {
+                    self.map_depth = 0;
+                    (backend.read()?, true)
+                }
+            };
+            match event {
+                Event::Key(event) if remap => {
+                    self.map_key(Key::from(event));
+                    continue;
+                }
+                // Keys waiting for a mapping come before this event.
+                _ if !self.typed.is_empty() => {
+                    self.input.push_front((event, remap));
+                    self.resolve_typed();
+                    continue;
+                }
+                _ => {}
+            }
+            self.message = None;
+            if let Event::Resize(cols, rows) = event {
+                self.screen_cols = cols as usize;
+                self.screen_rows = rows as usize;
+                let area = self.window_area();
+                self.layout.equalize(area);
+                for tab in &mut self.tabs {
+                    tab.layout.equalize(area);
+                }
+                self.fit_windows();
+                continue;
+            };
+
+            let action = self.process_input(event);
+            let result = self.apply(action);
+            if self.handle(result) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Runs an ex command outside of the editing loop, like the ones given
+    // with `-c`. Returns true if it quit the editor.
+    pub fn run_command(&mut self, command: &str) -> bool {
+        let result = self.execute(command);
+        self.scroll_to_cursor();
+        self.handle(result)
+    }
+
+    // Carries out or shows the outcome of an action or command. Returns true
+    // if the editor should quit.
+    fn handle(&mut self, result: Result<Effect, String>) -> bool {
+        match result {
+            Ok(Effect::Quit) => return true,
+            Ok(Effect::Message(message)) => self.message = Some(message),
+            // Other buffers may still keep the editor from quitting.
+            Ok(Effect::Write(request)) => match self.write(&request) {
+                Ok(_) if request.quit && !self.only_window_left() => {
+                    let _ = self.close_window(self.window);
+                }
+                Ok(_) if request.quit => match self.check_hidden() {
+                    Ok(()) => return true,
+                    Err(err) => self.message = Some(err),
+                },
+                Ok(message) | Err(message) => self.message = Some(message),
+            },
+            Ok(Effect::None | Effect::Redraw) => {}
+            Err(err) => self.message = Some(err),
+        }
+        false
+    }
+
+    // Carries out an action on the state of the editor. Anything that has to
+    // happen outside of it, like writing a file, is left to the caller.
+    pub fn apply(&mut self, action: EditorAction) -> Result<Effect, String> {
+        let effect = match action {
+            EditorAction::Quit => return self.quit_window(false),
+            EditorAction::Window(command) => return self.window_command(command),
+            EditorAction::NextTab => return self.cycle_tab(true),
+            EditorAction::PreviousTab => return self.cycle_tab(false),
+            EditorAction::AlternateBuffer => {
+                self.switch_buffer(self.alternate.ok_or("No alternate file")?)?;
+                Effect::Redraw
+            }
+            EditorAction::MoveLeft => {
+                self.file.col_pos = self.char_before();
+                Effect::Redraw
+            }
+            EditorAction::MoveDown => {
+                if self.file.row_pos + 1 < self.file.lines.len() {
+                    self.file.row_pos += 1;
+                }
+                self.clamp_cursor();
+                Effect::Redraw
+            }
+            EditorAction::MoveUp => {
+                self.file.row_pos = self.file.row_pos.saturating_sub(1);
+                self.clamp_cursor();
+                Effect::Redraw
+            }
+            EditorAction::MoveRight => {
+                self.file.col_pos = self.char_after();
+                Effect::Redraw
+            }
+            EditorAction::MoveToStartOfLine => {
+                self.file.col_pos = 0;
+                Effect::Redraw
+            }
+            EditorAction::MoveToEndOfLine => {
+                self.file.col_pos = self.file.lines[self.file.row_pos].chars.len();
+                Effect::Redraw
+            }
+            EditorAction::MoveDisplayDown => {
+                self.move_display_line(true);
+                Effect::Redraw
+            }
+            EditorAction::MoveDisplayUp => {
+                self.move_display_line(false);
+                Effect::Redraw
+            }
+            EditorAction::MoveToStartOfDisplayLine => {
+                self.move_to_display_start();
+                Effect::Redraw
+            }
+            EditorAction::MoveToEndOfDisplayLine => {
+                self.move_to_display_end();
+                Effect::Redraw
+            }
+            EditorAction::ScrollDown(amount) => {
+                self.scroll_down(amount);
+                Effect::Redraw
+            }
+            EditorAction::ScrollUp(amount) => {
+                self.scroll_up(amount);
+                Effect::Redraw
+            }
+            EditorAction::ScrollCursorTo(line) => {
+                self.scroll_cursor_to(line);
+                Effect::Redraw
+            }
+            EditorAction::InsertMode => {
+                self.mode = EditorMode::Insert;
+                Effect::Redraw
+            }
+            EditorAction::Append => {
+                self.mode = EditorMode::Insert;
+                self.file.col_pos = self.char_after();
+                Effect::Redraw
+            }
+            EditorAction::CommandMode => {
+                self.mode = EditorMode::Command;
+                Effect::Redraw
+            }
+            EditorAction::Save => self.write_command(None, false, "", false)?,
+            EditorAction::NormalMode => {
+                self.mode = EditorMode::Normal;
+                Effect::Redraw
+            }
+            EditorAction::Backspace => {
+                let before = self.char_before();
+                let file = &mut self.file;
+                if file.col_pos > 0 {
+                    file.col_pos = before;
+                    file.lines[file.row_pos].chars.remove(file.col_pos);
+                    file.edited(file.row_pos, 1, 1);
+                } else if file.row_pos > 0 {
+                    let current_line = file.lines.remove(file.row_pos);
+                    file.row_pos -= 1;
+                    file.col_pos = file.lines[file.row_pos].chars.len();
+                    file.lines[file.row_pos].chars.push_str(&current_line.chars);
+                    file.edited(file.row_pos, 2, 1);
+                }
+                Effect::Redraw
+            }
+            EditorAction::SplitLine => {
+                self.split_line();
+                Effect::Redraw
+            }
+            EditorAction::InsertChar(c) => {
+                self.insert_char(c);
+                Effect::Redraw
+            }
+            EditorAction::Reindent(start, end) => {
+                self.reindent(start, end);
+                Effect::Redraw
+            }
+            EditorAction::CommandChar(c) => {
+                self.command.push(c);
+                Effect::Redraw
+            }
+            EditorAction::CommandEnter => {
+                let command = mem::take(&mut self.command);
+                self.mode = EditorMode::Normal;
+                self.execute(&command)?
+            }
+            EditorAction::Operator(_) | EditorAction::NoOp => return Ok(Effect::None),
+        };
+
+        // Edits may have moved the cursor out of view or into the
+        // `scrolloff` margins.
+        self.scroll_to_cursor();
+        Ok(effect)
+    }
+}
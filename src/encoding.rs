@@ -0,0 +1,140 @@
+// The encodings that files can be read and written in, the values of the
+// `fileencoding` option.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Option<Encoding> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16le" => Some(Encoding::Utf16Le),
+            "utf-16" | "utf-16be" => Some(Encoding::Utf16Be),
+            "latin1" | "iso-8859-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+
+    fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => b"\xef\xbb\xbf",
+            Encoding::Utf16Le => b"\xff\xfe",
+            Encoding::Utf16Be => b"\xfe\xff",
+            Encoding::Latin1 => b"",
+        }
+    }
+
+    // Returns None if `bytes` are not valid in this encoding.
+    pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    return None;
+                }
+                let units = bytes.chunks(2).map(|pair| match self {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                });
+                char::decode_utf16(units).collect::<Result<_, _>>().ok()
+            }
+            Encoding::Latin1 => Some(bytes.iter().map(|&b| b as char).collect()),
+        }
+    }
+
+    // Encodes `text`, starting with a byte order mark if `bom` is set.
+    pub fn encode(&self, text: &str, bom: bool) -> Result<Vec<u8>, String> {
+        let mut bytes = match bom {
+            true => self.bom().to_vec(),
+            false => Vec::new(),
+        };
+        match self {
+            Encoding::Utf8 => bytes.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le => bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+            Encoding::Utf16Be => bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+            Encoding::Latin1 => {
+                for c in text.chars() {
+                    let b = u8::try_from(c)
+                        .map_err(|_| format!("cannot convert {:?} to {}", c, self.name()))?;
+                    bytes.push(b);
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+// Decodes the contents of a file, returning the text, its encoding and
+// whether it started with a byte order mark. Unless `encoding` is given, a
+// byte order mark decides, then text with NUL bytes in every other position
+// is taken to be UTF-16. Otherwise it is UTF-8 if it is valid as such, and
+// Latin-1 if not.
+pub fn decode(
+    bytes: &[u8],
+    encoding: Option<Encoding>,
+) -> Result<(String, Encoding, bool), String> {
+    if let Some(encoding) = encoding {
+        let (rest, bom) = match bytes.strip_prefix(encoding.bom()) {
+            Some(rest) if !encoding.bom().is_empty() => (rest, true),
+            _ => (bytes, false),
+        };
+        return match encoding.decode(rest) {
+            Some(text) => Ok((text, encoding, bom)),
+            None => Err(format!("Invalid {} text", encoding.name())),
+        };
+    }
+    for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+        if let Some(rest) = bytes.strip_prefix(encoding.bom()) {
+            if let Some(text) = encoding.decode(rest) {
+                return Ok((text, encoding, true));
+            }
+        }
+    }
+    if let Some(encoding) = guess_utf16(bytes) {
+        if let Some(text) = encoding.decode(bytes) {
+            return Ok((text, encoding, false));
+        }
+    }
+    match String::from_utf8(bytes.to_vec()) {
+        Ok(text) => Ok((text, Encoding::Utf8, false)),
+        Err(_) => Ok((
+            Encoding::Latin1.decode(bytes).unwrap(),
+            Encoding::Latin1,
+            false,
+        )),
+    }
+}
+
+// Mostly ASCII text in UTF-16 has a NUL byte in every other position.
+fn guess_utf16(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.is_empty() || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.len() / 2;
+    let zeros = |parity| {
+        bytes
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count()
+    };
+    match (zeros(0), zeros(1)) {
+        (0, odd) if odd * 2 > units => Some(Encoding::Utf16Le),
+        (even, 0) if even * 2 > units => Some(Encoding::Utf16Be),
+        _ => None,
+    }
+}
@@ -0,0 +1,188 @@
+mod syntax;
+
+use crate::Line;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+pub use syntax::{LineState, Syntax};
+
+const BUILTIN_SYNTAXES: [&str; 4] = [
+    include_str!("syntaxes/rust.toml"),
+    include_str!("syntaxes/toml.toml"),
+    include_str!("syntaxes/markdown.toml"),
+    include_str!("syntaxes/sh.toml"),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TokenClass {
+    Normal,
+    Comment,
+    Keyword,
+    String,
+    Number,
+    Type,
+    Function,
+    Constant,
+    Macro,
+    Label,
+    PreProc,
+    Variable,
+    Special,
+    Title,
+    Emphasis,
+    Strong,
+    Link,
+    Code,
+}
+
+impl TokenClass {
+    pub fn from_name(name: &str) -> Option<TokenClass> {
+        Some(match name {
+            "normal" => TokenClass::Normal,
+            "comment" => TokenClass::Comment,
+            "keyword" => TokenClass::Keyword,
+            "string" => TokenClass::String,
+            "number" => TokenClass::Number,
+            "type" => TokenClass::Type,
+            "function" => TokenClass::Function,
+            "constant" => TokenClass::Constant,
+            "macro" => TokenClass::Macro,
+            "label" => TokenClass::Label,
+            "preproc" => TokenClass::PreProc,
+            "variable" => TokenClass::Variable,
+            "special" => TokenClass::Special,
+            "title" => TokenClass::Title,
+            "emphasis" => TokenClass::Emphasis,
+            "strong" => TokenClass::Strong,
+            "link" => TokenClass::Link,
+            "code" => TokenClass::Code,
+            _ => return None,
+        })
+    }
+
+    // The highlight group a theme styles this class with.
+    pub fn group(self) -> &'static str {
+        match self {
+            TokenClass::Normal => "Normal",
+            TokenClass::Comment => "Comment",
+            TokenClass::Keyword => "Keyword",
+            TokenClass::String => "String",
+            TokenClass::Number => "Number",
+            TokenClass::Type => "Type",
+            TokenClass::Function => "Function",
+            TokenClass::Constant => "Constant",
+            TokenClass::Macro => "Macro",
+            TokenClass::Label => "Label",
+            TokenClass::PreProc => "PreProc",
+            TokenClass::Variable => "Identifier",
+            TokenClass::Special => "Special",
+            TokenClass::Title => "Title",
+            TokenClass::Emphasis => "Italic",
+            TokenClass::Strong => "Bold",
+            TokenClass::Link => "Underlined",
+            TokenClass::Code => "Code",
+        }
+    }
+}
+
+// A highlighted range of a line, in bytes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub class: TokenClass,
+}
+
+// Loads the built-in syntaxes followed by the grammars in the `syntax`
+// directory of `config_dir`, if given. A user grammar replaces a built-in
+// one with the same name. Grammars that fail to load are reported in the
+// returned errors.
+pub fn load_syntaxes(config_dir: Option<&Path>) -> (Vec<Rc<Syntax>>, Vec<String>) {
+    let mut syntaxes: Vec<Rc<Syntax>> = BUILTIN_SYNTAXES
+        .iter()
+        .map(|source| Rc::new(Syntax::from_toml(source).expect("invalid built-in syntax")))
+        .collect();
+    let mut errors = Vec::new();
+
+    let Some(dir) = config_dir.map(|dir| dir.join("syntax")) else {
+        return (syntaxes, errors);
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (syntaxes, errors);
+    };
+    let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+    for path in paths
+        .iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+    {
+        let loaded = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| Syntax::from_toml(&source));
+        match loaded {
+            Ok(syntax) => {
+                syntaxes.retain(|s| s.name != syntax.name);
+                syntaxes.push(Rc::new(syntax));
+            }
+            Err(err) => errors.push(format!("{}: {}", path.display(), err)),
+        }
+    }
+    (syntaxes, errors)
+}
+
+pub fn syntax_for(syntaxes: &[Rc<Syntax>], filename: &str) -> Option<Rc<Syntax>> {
+    syntaxes.iter().rev().find(|s| s.matches(filename)).cloned()
+}
+
+// Highlights the lines of a single file. The lexer state at the start of
+// every line is cached so that only lines below an edit need to be scanned
+// again, and only as far down as the screen reaches.
+pub struct Highlighter {
+    syntax: Rc<Syntax>,
+    states: Vec<LineState>,
+}
+
+impl Highlighter {
+    pub fn new(syntax: Rc<Syntax>) -> Highlighter {
+        Highlighter {
+            syntax,
+            states: vec![LineState::Normal],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.syntax.name
+    }
+
+    // Lines `row..row + old` were replaced by `row..row + new`. Returns true if
+    // the lines after them now start in a different state, which means they
+    // have to be highlighted again too.
+    pub fn edit(&mut self, lines: &[Line], row: usize, old: usize, new: usize) -> bool {
+        if self.states.len() <= row {
+            return true;
+        }
+        let before = self.states.get(row + old).cloned();
+        self.states.truncate(row + 1);
+        for line in lines.iter().skip(row).take(new) {
+            let (_, state) = self
+                .syntax
+                .tokenize(&line.chars, self.states.last().unwrap());
+            self.states.push(state);
+        }
+        before.is_none() || before.as_ref() != self.states.get(row + new)
+    }
+
+    pub fn highlight(&mut self, lines: &[Line], rows: Range<usize>) -> Vec<Vec<Span>> {
+        let end = rows.end.min(lines.len());
+        while self.states.len() < end {
+            let row = self.states.len() - 1;
+            let (_, state) = self.syntax.tokenize(&lines[row].chars, &self.states[row]);
+            self.states.push(state);
+        }
+        (rows.start.min(end)..end)
+            .map(|row| self.syntax.tokenize(&lines[row].chars, &self.states[row]).0)
+            .collect()
+    }
+}
@@ -0,0 +1,348 @@
+use super::{Span, TokenClass};
+use regex::{Captures, Regex};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use toml::{Table, Value};
+
+// A grammar is an ordered list of rules. At every position the rule whose
+// match starts first wins, with ties going to the rule listed first.
+// Keyword lists are turned into rules placed before all other rules.
+pub struct Syntax {
+    pub name: String,
+    extensions: Vec<String>,
+    filenames: Vec<String>,
+    rules: Vec<Rule>,
+    // Closing patterns of regions whose `end` refers back to the text matched
+    // by `start`, keyed by the substituted pattern.
+    dynamic_ends: RefCell<HashMap<String, Regex>>,
+}
+
+struct Rule {
+    class: TokenClass,
+    pattern: Pattern,
+}
+
+enum Pattern {
+    // A token that never spans lines. If the regex has a capture group, only
+    // the text of the first group is highlighted.
+    Match(Regex),
+    Region(Region),
+}
+
+struct Region {
+    start: Regex,
+    // `None` for regions that run until the end of the line.
+    end: Option<String>,
+    escape: Option<char>,
+    nested: bool,
+    multiline: bool,
+    close: Option<Regex>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum LineState {
+    Normal,
+    Region {
+        rule: usize,
+        depth: usize,
+        close: Option<String>,
+    },
+}
+
+fn string_list(table: &Table, key: &str) -> Result<Vec<String>, String> {
+    match table.get(key) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s.clone()),
+                _ => Err(format!("`{}` must be a list of strings", key)),
+            })
+            .collect(),
+        Some(_) => Err(format!("`{}` must be a list of strings", key)),
+    }
+}
+
+fn string_field<'a>(table: &'a Table, key: &str) -> Result<Option<&'a str>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(format!("`{}` must be a string", key)),
+    }
+}
+
+fn bool_field(table: &Table, key: &str) -> Result<bool, String> {
+    match table.get(key) {
+        None => Ok(false),
+        Some(Value::Boolean(b)) => Ok(*b),
+        Some(_) => Err(format!("`{}` must be a boolean", key)),
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|err| format!("invalid pattern `{}`: {}", pattern, err))
+}
+
+fn class_field(table: &Table) -> Result<TokenClass, String> {
+    let name = string_field(table, "class")?.ok_or("missing `class`")?;
+    TokenClass::from_name(name).ok_or_else(|| format!("unknown class `{}`", name))
+}
+
+// Builds the regex that finds the end of a region: escaped characters are
+// skipped, and for nested regions further openings are counted.
+fn close_pattern(region_start: &str, end: &str, escape: Option<char>, nested: bool) -> String {
+    let mut alternatives = Vec::new();
+    if let Some(escape) = escape {
+        alternatives.push(format!("(?P<esc>{}.)", regex::escape(&escape.to_string())));
+    }
+    if nested {
+        alternatives.push(format!("(?P<open>{})", region_start));
+    }
+    alternatives.push(format!("(?P<close>{})", end));
+    alternatives.join("|")
+}
+
+impl Rule {
+    fn from_table(table: &Table) -> Result<Rule, String> {
+        let class = class_field(table)?;
+        if let Some(pattern) = string_field(table, "match")? {
+            return Ok(Rule {
+                class,
+                pattern: Pattern::Match(compile(pattern)?),
+            });
+        }
+        let start = string_field(table, "start")?.ok_or("missing `match` or `start`")?;
+        let end = string_field(table, "end")?.map(String::from);
+        let escape = match string_field(table, "escape")? {
+            Some(s) if s.chars().count() == 1 => s.chars().next(),
+            Some(_) => return Err("`escape` must be a single character".to_string()),
+            None => None,
+        };
+        let nested = bool_field(table, "nested")?;
+        let close = match &end {
+            Some(end) if !end.contains("\\1") => {
+                Some(compile(&close_pattern(start, end, escape, nested))?)
+            }
+            _ => None,
+        };
+        Ok(Rule {
+            class,
+            pattern: Pattern::Region(Region {
+                start: compile(start)?,
+                end,
+                escape,
+                nested,
+                multiline: bool_field(table, "multiline")?,
+                close,
+            }),
+        })
+    }
+}
+
+impl Syntax {
+    pub fn from_toml(source: &str) -> Result<Syntax, String> {
+        let table: Table = source
+            .parse()
+            .map_err(|err: toml::de::Error| err.to_string())?;
+        let name = string_field(&table, "name")?
+            .ok_or("missing `name`")?
+            .to_string();
+
+        let mut rules = Vec::new();
+        if let Some(keywords) = table.get("keywords") {
+            let keywords = keywords.as_table().ok_or("`keywords` must be a table")?;
+            for (class_name, _) in keywords {
+                let class = TokenClass::from_name(class_name)
+                    .ok_or_else(|| format!("unknown class `{}`", class_name))?;
+                let words = string_list(keywords, class_name)?;
+                let words: Vec<String> = words.iter().map(|w| regex::escape(w)).collect();
+                rules.push(Rule {
+                    class,
+                    pattern: Pattern::Match(compile(&format!(r"\b(?:{})\b", words.join("|")))?),
+                });
+            }
+        }
+        if let Some(list) = table.get("rules") {
+            let list = list
+                .as_array()
+                .ok_or("`rules` must be an array of tables")?;
+            for (i, rule) in list.iter().enumerate() {
+                let rule = rule
+                    .as_table()
+                    .ok_or("`rules` must be an array of tables")?;
+                rules.push(
+                    Rule::from_table(rule).map_err(|err| format!("rule {}: {}", i + 1, err))?,
+                );
+            }
+        }
+
+        Ok(Syntax {
+            name,
+            extensions: string_list(&table, "extensions")?,
+            filenames: string_list(&table, "filenames")?,
+            rules,
+            dynamic_ends: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let filename = path.rsplit('/').next().unwrap_or(path);
+        self.filenames.iter().any(|f| f == filename)
+            || filename
+                .rsplit_once('.')
+                .is_some_and(|(_, ext)| self.extensions.iter().any(|e| e == ext))
+    }
+
+    fn opened(&self, rule: usize, captures: &Captures) -> LineState {
+        let Pattern::Region(region) = &self.rules[rule].pattern else {
+            unreachable!()
+        };
+        let close = match (&region.end, &region.close) {
+            (Some(end), None) => {
+                let captured = captures.get(1).map_or("", |m| m.as_str());
+                let end = end.replace("\\1", &regex::escape(captured));
+                Some(close_pattern(
+                    region.start.as_str(),
+                    &end,
+                    region.escape,
+                    region.nested,
+                ))
+            }
+            _ => None,
+        };
+        LineState::Region {
+            rule,
+            depth: 1,
+            close,
+        }
+    }
+
+    // Returns the offset just past the end of the region, or `None` if it
+    // continues past the end of the line.
+    fn find_close(&self, text: &str, pos: usize, state: &mut LineState) -> Option<usize> {
+        let LineState::Region { rule, depth, close } = state else {
+            unreachable!()
+        };
+        let Pattern::Region(region) = &self.rules[*rule].pattern else {
+            unreachable!()
+        };
+        let mut dynamic_ends = self.dynamic_ends.borrow_mut();
+        let regex = match (close, &region.close) {
+            (_, Some(regex)) => regex,
+            (Some(pattern), None) => dynamic_ends.entry(pattern.clone()).or_insert_with(|| {
+                Regex::new(pattern).unwrap_or_else(|_| Regex::new("$^").unwrap())
+            }),
+            (None, None) => return None,
+        };
+        let mut pos = pos;
+        while let Some(captures) = regex.captures_at(text, pos) {
+            let whole = captures.get(0).unwrap();
+            if captures.name("open").is_some() {
+                *depth += 1;
+            } else if captures.name("close").is_some() {
+                *depth -= 1;
+                if *depth == 0 {
+                    return Some(whole.end());
+                }
+            }
+            pos = match whole.end() > pos {
+                true => whole.end(),
+                false => pos + text[pos..].chars().next()?.len_utf8(),
+            };
+        }
+        None
+    }
+
+    pub fn tokenize(&self, text: &str, state: &LineState) -> (Vec<Span>, LineState) {
+        let mut spans = Vec::new();
+        let mut state = state.clone();
+        let mut pos = 0;
+        let mut region_start = 0;
+        // The next match of every rule at or after `pos`, recomputed lazily
+        // once `pos` has moved past it.
+        let mut next: Vec<Option<Option<Captures>>> = (0..self.rules.len()).map(|_| None).collect();
+
+        loop {
+            if let LineState::Region { rule, .. } = state {
+                let class = self.rules[rule].class;
+                match self.find_close(text, pos, &mut state) {
+                    Some(end) => {
+                        spans.push(Span {
+                            start: region_start,
+                            end,
+                            class,
+                        });
+                        pos = end;
+                        state = LineState::Normal;
+                    }
+                    None => {
+                        spans.push(Span {
+                            start: region_start,
+                            end: text.len(),
+                            class,
+                        });
+                        let Pattern::Region(region) = &self.rules[rule].pattern else {
+                            unreachable!()
+                        };
+                        if region.end.is_none() || !region.multiline {
+                            state = LineState::Normal;
+                        }
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            let mut best: Option<(usize, usize)> = None;
+            for (i, rule) in self.rules.iter().enumerate() {
+                let stale = match &next[i] {
+                    None => true,
+                    Some(Some(c)) => c.get(0).unwrap().start() < pos,
+                    Some(None) => false,
+                };
+                if stale {
+                    let regex = match &rule.pattern {
+                        Pattern::Match(regex) => regex,
+                        Pattern::Region(region) => &region.start,
+                    };
+                    next[i] = Some(regex.captures_at(text, pos));
+                }
+                if let Some(Some(c)) = &next[i] {
+                    let start = c.get(0).unwrap().start();
+                    if best.is_none_or(|(_, s)| start < s) {
+                        best = Some((i, start));
+                    }
+                }
+            }
+            let Some((i, start)) = best else { break };
+            let captures = next[i].take().flatten().unwrap();
+            let whole = captures.get(0).unwrap();
+
+            match &self.rules[i].pattern {
+                Pattern::Match(_) => {
+                    let m = captures.get(1).unwrap_or(whole);
+                    if !m.is_empty() {
+                        spans.push(Span {
+                            start: m.start(),
+                            end: m.end(),
+                            class: self.rules[i].class,
+                        });
+                    }
+                    pos = whole.end();
+                }
+                Pattern::Region(_) => {
+                    state = self.opened(i, &captures);
+                    region_start = start;
+                    pos = whole.end();
+                }
+            }
+            if whole.is_empty() {
+                match text[pos..].chars().next() {
+                    Some(c) => pos += c.len_utf8(),
+                    None => break,
+                }
+            }
+        }
+        (spans, state)
+    }
+}
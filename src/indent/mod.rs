@@ -0,0 +1,109 @@
+mod rust;
+
+use crate::options::OptionValue;
+use crate::Line;
+use std::ops::Range;
+
+pub use rust::RustIndenter;
+
+pub struct IndentOptions {
+    pub shiftwidth: usize,
+    pub tabstop: usize,
+    pub expandtab: bool,
+}
+
+pub trait Indenter {
+    // Recomputes the indentation of every line in `range`. Lines before the
+    // range are taken as they are and only used as context.
+    fn reindent(&self, lines: &mut [Line], range: Range<usize>, opts: &IndentOptions);
+
+    // The local options that buffers of the filetype get, before those of
+    // the config file.
+    fn options(&self) -> Vec<(&'static str, OptionValue)>;
+}
+
+pub fn indenter_for(filetype: &str) -> Option<Box<dyn Indenter>> {
+    match filetype {
+        "rust" => Some(Box::new(RustIndenter)),
+        _ => None,
+    }
+}
+
+pub fn is_opener(c: char) -> bool {
+    matches!(c, '{' | '(' | '[')
+}
+
+pub fn is_closer(c: char) -> bool {
+    matches!(c, '}' | ')' | ']')
+}
+
+pub fn matching_closer(c: char) -> Option<char> {
+    match c {
+        '{' => Some('}'),
+        '(' => Some(')'),
+        '[' => Some(']'),
+        _ => None,
+    }
+}
+
+pub fn leading_whitespace(text: &str) -> &str {
+    &text[..text.len() - text.trim_start_matches([' ', '\t']).len()]
+}
+
+pub fn indent_width(text: &str, tabstop: usize) -> usize {
+    leading_whitespace(text)
+        .chars()
+        .fold(0, |width, c| match c {
+            '\t' => width + tabstop - width % tabstop,
+            _ => width + 1,
+        })
+}
+
+pub fn indent_string(width: usize, opts: &IndentOptions) -> String {
+    if opts.expandtab || opts.tabstop == 0 {
+        " ".repeat(width)
+    } else {
+        "\t".repeat(width / opts.tabstop) + &" ".repeat(width % opts.tabstop)
+    }
+}
+
+// Replaces the leading whitespace of `text` with an indent of `width` columns.
+// Blank lines are emptied instead of being padded with whitespace.
+pub fn set_indent(text: &mut String, width: usize, opts: &IndentOptions) {
+    let old = leading_whitespace(text).len();
+    if old == text.len() {
+        text.clear();
+    } else {
+        text.replace_range(..old, &indent_string(width, opts));
+    }
+}
+
+// Walks backwards from `row`, `col` to the bracket that is still open at that
+// position and returns the indent width of the line it sits on.
+pub fn open_bracket_indent(
+    lines: &[Line],
+    row: usize,
+    col: usize,
+    tabstop: usize,
+) -> Option<usize> {
+    let mut depth = 0;
+    for r in (0..=row).rev() {
+        let text = &lines[r].chars;
+        let end = if r == row {
+            col.min(text.len())
+        } else {
+            text.len()
+        };
+        for c in text[..end].chars().rev() {
+            if is_closer(c) {
+                depth += 1;
+            } else if is_opener(c) {
+                if depth == 0 {
+                    return Some(indent_width(text, tabstop));
+                }
+                depth -= 1;
+            }
+        }
+    }
+    None
+}
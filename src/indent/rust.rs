@@ -0,0 +1,171 @@
+use super::{indent_width, is_closer, is_opener, set_indent, IndentOptions, Indenter};
+use crate::options::OptionValue;
+use crate::Line;
+use std::ops::Range;
+
+pub struct RustIndenter;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Code,
+    BlockComment(usize),
+    Str,
+    RawStr(usize),
+}
+
+struct LineScan {
+    state: State,
+    // The last two characters of code on the line, ignoring whitespace,
+    // comments and the contents of literals.
+    tail: [char; 2],
+}
+
+// Scans a single line starting in `state`, reporting every bracket that is
+// part of the code (as opposed to a string, char literal or comment).
+fn scan_line(text: &str, mut state: State, mut on_bracket: impl FnMut(char)) -> LineScan {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tail = [' ', ' '];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match state {
+            State::Code => match c {
+                '/' if next == Some('/') => break,
+                '/' if next == Some('*') => {
+                    state = State::BlockComment(1);
+                    i += 1;
+                }
+                '"' => state = State::Str,
+                'r' if !is_ident_char(i.checked_sub(1).map(|p| chars[p])) => {
+                    let hashes = chars[i + 1..].iter().take_while(|&&c| c == '#').count();
+                    if chars.get(i + 1 + hashes) == Some(&'"') {
+                        state = State::RawStr(hashes);
+                        i += hashes + 1;
+                    }
+                }
+                '\'' => {
+                    // Either a char literal or a lifetime. Only the former
+                    // needs to be skipped over.
+                    if next == Some('\\') {
+                        i += 2;
+                        while i < chars.len() && chars[i] != '\'' {
+                            i += 1;
+                        }
+                    } else if chars.get(i + 2) == Some(&'\'') {
+                        i += 2;
+                    }
+                    tail = [tail[1], '\''];
+                }
+                c if c.is_whitespace() => {}
+                c => {
+                    if is_opener(c) || is_closer(c) {
+                        on_bracket(c);
+                    }
+                    tail = [tail[1], c];
+                }
+            },
+            State::Str => match c {
+                '\\' => i += 1,
+                '"' => {
+                    state = State::Code;
+                    tail = [tail[1], '"'];
+                }
+                _ => {}
+            },
+            State::RawStr(hashes) => {
+                if c == '"' && chars[i + 1..].iter().take_while(|&&c| c == '#').count() >= hashes {
+                    state = State::Code;
+                    tail = [tail[1], '"'];
+                    i += hashes;
+                }
+            }
+            State::BlockComment(depth) => {
+                if c == '/' && next == Some('*') {
+                    state = State::BlockComment(depth + 1);
+                    i += 1;
+                } else if c == '*' && next == Some('/') {
+                    state = if depth == 1 {
+                        State::Code
+                    } else {
+                        State::BlockComment(depth - 1)
+                    };
+                    i += 1;
+                }
+            }
+        }
+        i += 1;
+    }
+    LineScan { state, tail }
+}
+
+fn is_ident_char(c: Option<char>) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+// Whether a line ending in `tail` leaves the statement unfinished, so the
+// next line is a continuation of it.
+fn continues(tail: [char; 2]) -> bool {
+    matches!(tail[1], '=' | '+' | '-' | '*' | '/' | '%' | '&' | '|' | '^') || tail == ['=', '>']
+}
+
+impl Indenter for RustIndenter {
+    // The recommended style of vim's Rust plugin.
+    fn options(&self) -> Vec<(&'static str, OptionValue)> {
+        vec![
+            ("autoindent", OptionValue::Bool(true)),
+            ("smartindent", OptionValue::Bool(true)),
+            ("expandtab", OptionValue::Bool(true)),
+            ("shiftwidth", OptionValue::Number(4)),
+        ]
+    }
+
+    fn reindent(&self, lines: &mut [Line], range: Range<usize>, opts: &IndentOptions) {
+        // Each open bracket remembers the bracket and the indent of the line
+        // it was opened on, so several brackets opened on one line only
+        // indent the following lines by a single level.
+        let mut stack: Vec<(char, usize)> = Vec::new();
+        let mut state = State::Code;
+        let mut tail = [' ', ' '];
+
+        for row in 0..range.end.min(lines.len()) {
+            let text = &mut lines[row].chars;
+            let trimmed = text.trim_start();
+            let blank = trimmed.is_empty();
+
+            if row >= range.start && matches!(state, State::Code | State::BlockComment(_)) {
+                let closes = trimmed.starts_with(is_closer);
+                let mut width = match stack.last() {
+                    Some(&(_, indent)) if closes => indent,
+                    Some(&(_, indent)) => indent + opts.shiftwidth,
+                    None => 0,
+                };
+                let in_block = matches!(stack.last(), None | Some(('{', _)));
+                if let State::BlockComment(_) = state {
+                    if trimmed.starts_with('*') {
+                        width += 1;
+                    }
+                } else if in_block
+                    && !closes
+                    && (continues(tail) || trimmed.starts_with('.') || trimmed.starts_with('?'))
+                {
+                    width += opts.shiftwidth;
+                }
+                set_indent(text, width, opts);
+            }
+
+            let indent = indent_width(text, opts.tabstop);
+            let scan = scan_line(text, state, |c| {
+                if is_opener(c) {
+                    stack.push((c, indent));
+                } else {
+                    stack.pop();
+                }
+            });
+            state = scan.state;
+            if !blank && scan.tail != [' ', ' '] {
+                tail = scan.tail;
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fmt;
+
+// A key together with the modifiers held down with it, in a canonical form
+// so that keys which are the same to vim compare equal: `<S-a>` is `A` and
+// `<C-D>` is `<C-d>`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::BackTab => {
+                modifiers |= KeyModifiers::SHIFT;
+                KeyCode::Tab
+            }
+            KeyCode::Char(c) => {
+                // The shift is already part of the character, except for
+                // letters typed with control.
+                let shifted = modifiers.contains(KeyModifiers::SHIFT);
+                modifiers.remove(KeyModifiers::SHIFT);
+                match c {
+                    c if modifiers.contains(KeyModifiers::CONTROL) => {
+                        KeyCode::Char(c.to_ascii_lowercase())
+                    }
+                    c if shifted => KeyCode::Char(c.to_ascii_uppercase()),
+                    c => KeyCode::Char(c),
+                }
+            }
+            code => code,
+        };
+        Key { code, modifiers }
+    }
+
+    pub fn char(c: char) -> Key {
+        Key::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    // The character this key types, if it is one without modifiers.
+    pub fn as_char(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c) if self.modifiers.is_empty() => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Key {
+        Key::new(event.code, event.modifiers)
+    }
+}
+
+impl From<Key> for KeyEvent {
+    fn from(key: Key) -> KeyEvent {
+        KeyEvent::new(key.code, key.modifiers)
+    }
+}
+
+fn named_key(name: &str) -> Option<KeyCode> {
+    let name = name.to_ascii_lowercase();
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+        return (1..=12).contains(&n).then_some(KeyCode::F(n));
+    }
+    Some(match name.as_str() {
+        "esc" => KeyCode::Esc,
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "bs" | "backspace" => KeyCode::Backspace,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "bar" => KeyCode::Char('|'),
+        "bslash" => KeyCode::Char('\\'),
+        "del" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ => return None,
+    })
+}
+
+// Parses what is between `<` and `>`, e.g. `Esc`, `C-d` or `S-Tab`.
+fn parse_special(name: &str) -> Option<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        modifiers |= match rest.as_bytes()[0].to_ascii_lowercase() {
+            b'c' => KeyModifiers::CONTROL,
+            b'm' | b'a' => KeyModifiers::ALT,
+            b's' => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        rest = &rest[2..];
+    }
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) if !modifiers.is_empty() => KeyCode::Char(c),
+        _ => named_key(rest)?,
+    };
+    Some(Key::new(code, modifiers))
+}
+
+// Parses keys written in vim notation, e.g. `dd`, `<C-w>j` or `<Esc>:w<CR>`.
+// A `<` that does not start a known key name stands for itself.
+pub fn parse_keys(keys: &str) -> Result<Vec<Key>, String> {
+    let mut result = Vec::new();
+    let mut rest = keys;
+    while let Some(c) = rest.chars().next() {
+        let special = rest
+            .strip_prefix('<')
+            .and_then(|r| r.split_once('>'))
+            .and_then(|(name, after)| Some((parse_special(name)?, after)));
+        match special {
+            Some((key, after)) => {
+                result.push(key);
+                rest = after;
+            }
+            None => {
+                result.push(Key::char(c));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if result.is_empty() {
+        return Err("empty key sequence".to_string());
+    }
+    Ok(result)
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.code {
+            KeyCode::Char(c) if self.modifiers.is_empty() && c != '<' && c != ' ' => {
+                return write!(f, "{}", c);
+            }
+            KeyCode::Char('<') => "lt".to_string(),
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            KeyCode::Enter => "CR".to_string(),
+            KeyCode::Backspace => "BS".to_string(),
+            KeyCode::Tab => "Tab".to_string(),
+            KeyCode::Delete => "Del".to_string(),
+            KeyCode::Insert => "Insert".to_string(),
+            KeyCode::Up => "Up".to_string(),
+            KeyCode::Down => "Down".to_string(),
+            KeyCode::Left => "Left".to_string(),
+            KeyCode::Right => "Right".to_string(),
+            KeyCode::Home => "Home".to_string(),
+            KeyCode::End => "End".to_string(),
+            KeyCode::PageUp => "PageUp".to_string(),
+            KeyCode::PageDown => "PageDown".to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            code => format!("{:?}", code),
+        };
+        write!(f, "<")?;
+        for (modifier, prefix) in [
+            (KeyModifiers::CONTROL, "C-"),
+            (KeyModifiers::ALT, "M-"),
+            (KeyModifiers::SHIFT, "S-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", prefix)?;
+            }
+        }
+        write!(f, "{}>", name)
+    }
+}
+
+// Writes keys in vim notation, the way `parse_keys` reads them.
+pub fn to_notation(keys: &[Key]) -> String {
+    keys.iter().map(Key::to_string).collect()
+}
@@ -0,0 +1,231 @@
+use crate::key::{self, Key};
+use std::collections::HashMap;
+
+// Maps key sequences to values. Looking up a sequence tells whether it is
+// bound, and whether it is also the start of a longer sequence.
+pub struct Trie<T> {
+    value: Option<T>,
+    children: HashMap<Key, Trie<T>>,
+}
+
+pub enum Lookup<'a, T> {
+    // No sequence starts with the keys.
+    None,
+    // The keys are the start of a longer sequence, but not bound themselves.
+    Prefix,
+    Exact(&'a T),
+    // The keys are bound, but also the start of a longer sequence.
+    Ambiguous(&'a T),
+}
+
+impl<T> Default for Trie<T> {
+    fn default() -> Trie<T> {
+        Trie {
+            value: None,
+            children: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Trie<T> {
+    pub fn insert(&mut self, keys: &[Key], value: T) {
+        let node = keys
+            .iter()
+            .fold(self, |node, key| node.children.entry(*key).or_default());
+        node.value = Some(value);
+    }
+
+    // Removes the value bound to `keys`, and the nodes that no longer lead to
+    // any value.
+    pub fn remove(&mut self, keys: &[Key]) -> Option<T> {
+        let Some((first, rest)) = keys.split_first() else {
+            return self.value.take();
+        };
+        let child = self.children.get_mut(first)?;
+        let value = child.remove(rest);
+        if child.value.is_none() && child.children.is_empty() {
+            self.children.remove(first);
+        }
+        value
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup<'_, T> {
+        let mut node = self;
+        for key in keys {
+            match node.children.get(key) {
+                Some(child) => node = child,
+                None => return Lookup::None,
+            }
+        }
+        match (&node.value, node.children.is_empty()) {
+            (Some(value), true) => Lookup::Exact(value),
+            (Some(value), false) => Lookup::Ambiguous(value),
+            (None, false) => Lookup::Prefix,
+            (None, true) => Lookup::None,
+        }
+    }
+
+    // The longest bound sequence that `keys` starts with, and its length.
+    pub fn longest(&self, keys: &[Key]) -> Option<(usize, &T)> {
+        let mut node = self;
+        let mut longest = node.value.as_ref().map(|value| (0, value));
+        for (i, key) in keys.iter().enumerate() {
+            match node.children.get(key) {
+                Some(child) => node = child,
+                None => break,
+            }
+            if let Some(value) = &node.value {
+                longest = Some((i + 1, value));
+            }
+        }
+        longest
+    }
+
+    // All values, in no particular order.
+    pub fn values(&self) -> Vec<&T> {
+        let mut values: Vec<&T> = self.value.iter().collect();
+        for child in self.children.values() {
+            values.extend(child.values());
+        }
+        values
+    }
+}
+
+// A mapping defined with `:map` and friends, or in the config file.
+#[derive(Clone)]
+pub struct Mapping {
+    pub lhs: Vec<Key>,
+    pub keys: Vec<Key>,
+    pub noremap: bool,
+}
+
+#[derive(Default)]
+pub struct Keymaps {
+    modes: HashMap<char, Trie<Mapping>>,
+}
+
+impl Keymaps {
+    pub fn get(&self, mode: char) -> Option<&Trie<Mapping>> {
+        self.modes.get(&mode)
+    }
+
+    pub fn map(&mut self, modes: &str, mapping: &Mapping) {
+        for mode in modes.chars() {
+            let trie = self.modes.entry(mode).or_default();
+            trie.insert(&mapping.lhs, mapping.clone());
+        }
+    }
+
+    // Returns false if there was no such mapping in any of the modes.
+    pub fn unmap(&mut self, modes: &str, lhs: &[Key]) -> bool {
+        let mut found = false;
+        for mode in modes.chars() {
+            if let Some(trie) = self.modes.get_mut(&mode) {
+                found |= trie.remove(lhs).is_some();
+            }
+        }
+        found
+    }
+
+    pub fn clear(&mut self, modes: &str) {
+        for mode in modes.chars() {
+            self.modes.remove(&mode);
+        }
+    }
+
+    // Lists the mappings of `modes` whose left-hand side starts with
+    // `prefix`, like `:map` does.
+    pub fn list(&self, modes: &str, prefix: &[Key]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for mode in modes.chars() {
+            let Some(trie) = self.modes.get(&mode) else {
+                continue;
+            };
+            let mut mappings: Vec<_> = trie
+                .values()
+                .into_iter()
+                .filter(|mapping| mapping.lhs.starts_with(prefix))
+                .map(|mapping| {
+                    let noremap = if mapping.noremap { "*" } else { "" };
+                    let lhs = key::to_notation(&mapping.lhs);
+                    (lhs, noremap, key::to_notation(&mapping.keys))
+                })
+                .collect();
+            mappings.sort();
+            for (lhs, noremap, rhs) in mappings {
+                lines.push(format!("{} {} {}{}", mode, lhs, noremap, rhs));
+            }
+        }
+        lines
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum MapCommand {
+    Map { noremap: bool },
+    Unmap,
+    Clear,
+}
+
+const MAP: MapCommand = MapCommand::Map { noremap: false };
+const NOREMAP: MapCommand = MapCommand::Map { noremap: true };
+
+// The commands of the `:map` family, with the shortest abbreviation each of
+// them can be written as and the modes they apply to.
+const MAP_COMMANDS: [(&str, usize, MapCommand, &str); 24] = [
+    ("map", 3, MAP, "nvo"),
+    ("noremap", 2, NOREMAP, "nvo"),
+    ("unmap", 3, MapCommand::Unmap, "nvo"),
+    ("mapclear", 4, MapCommand::Clear, "nvo"),
+    ("nmap", 2, MAP, "n"),
+    ("nnoremap", 2, NOREMAP, "n"),
+    ("nunmap", 3, MapCommand::Unmap, "n"),
+    ("nmapclear", 5, MapCommand::Clear, "n"),
+    ("vmap", 2, MAP, "v"),
+    ("vnoremap", 2, NOREMAP, "v"),
+    ("vunmap", 2, MapCommand::Unmap, "v"),
+    ("vmapclear", 5, MapCommand::Clear, "v"),
+    ("omap", 2, MAP, "o"),
+    ("onoremap", 3, NOREMAP, "o"),
+    ("ounmap", 2, MapCommand::Unmap, "o"),
+    ("omapclear", 5, MapCommand::Clear, "o"),
+    ("imap", 2, MAP, "i"),
+    ("inoremap", 3, NOREMAP, "i"),
+    ("iunmap", 2, MapCommand::Unmap, "i"),
+    ("imapclear", 5, MapCommand::Clear, "i"),
+    ("cmap", 2, MAP, "c"),
+    ("cnoremap", 3, NOREMAP, "c"),
+    ("cunmap", 2, MapCommand::Unmap, "c"),
+    ("cmapclear", 5, MapCommand::Clear, "c"),
+];
+
+// Parses the name of a `:map` family command like `nnoremap`, `iu` or
+// `map!` into the command and the modes it applies to. With a `!`, the
+// commands without a mode prefix apply to insert and command-line mode.
+pub fn map_command(name: &str) -> Option<(MapCommand, &'static str)> {
+    let (name, bang) = match name.strip_suffix('!') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    let (_, _, command, modes) = MAP_COMMANDS
+        .iter()
+        .find(|(full, min, _, _)| name.len() >= *min && full.starts_with(name))?;
+    match (bang, *modes) {
+        (false, modes) => Some((*command, modes)),
+        (true, "nvo") => Some((*command, "ic")),
+        (true, _) => None,
+    }
+}
+
+// Replaces `<leader>` in keys written in vim notation with `leader`.
+pub fn expand_leader(keys: &str, leader: &str) -> String {
+    let mut result = String::new();
+    let mut rest = keys;
+    while let Some(i) = rest.to_ascii_lowercase().find("<leader>") {
+        result.push_str(&rest[..i]);
+        result.push_str(leader);
+        rest = &rest[i + "<leader>".len()..];
+    }
+    result.push_str(rest);
+    result
+}
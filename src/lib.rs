@@ -0,0 +1,22 @@
+pub mod buffer;
+pub mod cli;
+mod command;
+pub mod config;
+pub mod editor;
+pub mod encoding;
+pub mod highlight;
+pub mod indent;
+pub mod key;
+pub mod keymap;
+pub mod options;
+mod render;
+mod save;
+pub mod screen;
+pub mod tab;
+pub mod terminal;
+pub mod theme;
+pub mod window;
+pub mod wrap;
+
+pub use buffer::{EditorFile, Line, Sign};
+pub use editor::{Editor, EditorAction, EditorMode, Effect, WriteRequest};
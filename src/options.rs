@@ -0,0 +1,543 @@
+use crate::encoding::Encoding;
+use std::collections::HashMap;
+use std::fmt;
+use toml::Value;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionValue {
+    Bool(bool),
+    Number(i64),
+    String(String),
+    List(Vec<String>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scope {
+    Global,
+    Window,
+    Buffer,
+}
+
+pub enum Default {
+    Bool(bool),
+    Number(i64),
+    String(&'static str),
+    // Comma separated, like a list is written in `:set`.
+    List(&'static str),
+}
+
+pub struct OptionDef {
+    pub name: &'static str,
+    pub short: &'static str,
+    pub scope: Scope,
+    pub default: Default,
+}
+
+pub const OPTIONS: &[OptionDef] = &[
+    OptionDef {
+        name: "backup",
+        short: "bk",
+        scope: Scope::Global,
+        default: Default::Bool(false),
+    },
+    OptionDef {
+        name: "backupext",
+        short: "bex",
+        scope: Scope::Global,
+        default: Default::String("~"),
+    },
+    OptionDef {
+        name: "autoindent",
+        short: "ai",
+        scope: Scope::Buffer,
+        default: Default::Bool(false),
+    },
+    OptionDef {
+        name: "bomb",
+        short: "bomb",
+        scope: Scope::Buffer,
+        default: Default::Bool(false),
+    },
+    OptionDef {
+        name: "breakindent",
+        short: "bri",
+        scope: Scope::Window,
+        default: Default::Bool(false),
+    },
+    OptionDef {
+        name: "endofline",
+        short: "eol",
+        scope: Scope::Buffer,
+        default: Default::Bool(true),
+    },
+    OptionDef {
+        name: "expandtab",
+        short: "et",
+        scope: Scope::Buffer,
+        default: Default::Bool(false),
+    },
+    OptionDef {
+        name: "fileencoding",
+        short: "fenc",
+        scope: Scope::Buffer,
+        default: Default::String("utf-8"),
+    },
+    OptionDef {
+        name: "fileformat",
+        short: "ff",
+        scope: Scope::Buffer,
+        default: Default::String("unix"),
+    },
+    OptionDef {
+        name: "filetype",
+        short: "ft",
+        scope: Scope::Buffer,
+        default: Default::String(""),
+    },
+    OptionDef {
+        name: "fixendofline",
+        short: "fixeol",
+        scope: Scope::Buffer,
+        default: Default::Bool(true),
+    },
+    OptionDef {
+        name: "guicursor",
+        short: "gcr",
+        scope: Scope::Global,
+        default: Default::List("n-c:block,i:ver25"),
+    },
+    OptionDef {
+        name: "linebreak",
+        short: "lbr",
+        scope: Scope::Window,
+        default: Default::Bool(false),
+    },
+    OptionDef {
+        name: "mapleader",
+        short: "mapleader",
+        scope: Scope::Global,
+        default: Default::String("\\"),
+    },
+    OptionDef {
+        name: "number",
+        short: "nu",
+        scope: Scope::Window,
+        default: Default::Bool(false),
+    },
+    OptionDef {
+        name: "numberwidth",
+        short: "nuw",
+        scope: Scope::Window,
+        default: Default::Number(4),
+    },
+    OptionDef {
+        name: "readonly",
+        short: "ro",
+        scope: Scope::Buffer,
+        default: Default::Bool(false),
+    },
+    OptionDef {
+        name: "relativenumber",
+        short: "rnu",
+        scope: Scope::Window,
+        default: Default::Bool(false),
+    },
+    OptionDef {
+        name: "scrolljump",
+        short: "sj",
+        scope: Scope::Global,
+        default: Default::Number(1),
+    },
+    OptionDef {
+        name: "scrolloff",
+        short: "so",
+        scope: Scope::Window,
+        default: Default::Number(0),
+    },
+    OptionDef {
+        name: "shiftwidth",
+        short: "sw",
+        scope: Scope::Buffer,
+        default: Default::Number(8),
+    },
+    OptionDef {
+        name: "showbreak",
+        short: "sbr",
+        scope: Scope::Global,
+        default: Default::String(""),
+    },
+    OptionDef {
+        name: "showcmd",
+        short: "sc",
+        scope: Scope::Global,
+        default: Default::Bool(true),
+    },
+    OptionDef {
+        name: "sidescroll",
+        short: "ss",
+        scope: Scope::Global,
+        default: Default::Number(1),
+    },
+    OptionDef {
+        name: "sidescrolloff",
+        short: "siso",
+        scope: Scope::Window,
+        default: Default::Number(0),
+    },
+    OptionDef {
+        name: "signcolumn",
+        short: "scl",
+        scope: Scope::Window,
+        default: Default::String("auto"),
+    },
+    OptionDef {
+        name: "smartindent",
+        short: "si",
+        scope: Scope::Buffer,
+        default: Default::Bool(false),
+    },
+    OptionDef {
+        name: "tabstop",
+        short: "ts",
+        scope: Scope::Buffer,
+        default: Default::Number(8),
+    },
+    OptionDef {
+        name: "timeoutlen",
+        short: "tm",
+        scope: Scope::Global,
+        default: Default::Number(1000),
+    },
+    OptionDef {
+        name: "wrap",
+        short: "wrap",
+        scope: Scope::Window,
+        default: Default::Bool(false),
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static OptionDef> {
+    OPTIONS
+        .iter()
+        .find(|def| def.name == name || def.short == name)
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl OptionDef {
+    pub fn default_value(&self) -> OptionValue {
+        match self.default {
+            Default::Bool(b) => OptionValue::Bool(b),
+            Default::Number(n) => OptionValue::Number(n),
+            Default::String(s) => OptionValue::String(s.to_string()),
+            Default::List(list) => OptionValue::List(split_list(list)),
+        }
+    }
+
+    // Parses the text after `=` in `:set name=value`.
+    pub fn parse(&self, text: &str) -> Result<OptionValue, String> {
+        match self.default {
+            Default::Bool(_) => Err(format!("Invalid argument: {}={}", self.name, text)),
+            Default::Number(_) => text
+                .parse()
+                .map(OptionValue::Number)
+                .map_err(|_| format!("Number required after =: {}={}", self.name, text)),
+            Default::String(_) => Ok(OptionValue::String(text.to_string())),
+            Default::List(_) => Ok(OptionValue::List(split_list(text))),
+        }
+    }
+
+    // Converts a value from the config file.
+    pub fn parse_toml(&self, value: &Value) -> Result<OptionValue, String> {
+        let value = match (&self.default, value) {
+            (Default::Bool(_), Value::Boolean(b)) => OptionValue::Bool(*b),
+            (Default::Number(_), Value::Integer(n)) => OptionValue::Number(*n),
+            (Default::String(_), Value::String(s)) => OptionValue::String(s.clone()),
+            (Default::List(_), Value::String(s)) => OptionValue::List(split_list(s)),
+            (Default::List(_), Value::Array(items)) => OptionValue::List(
+                items
+                    .iter()
+                    .map(|item| item.as_str().map(String::from))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| format!("`{}` must be a list of strings", self.name))?,
+            ),
+            (Default::Bool(_), _) => return Err(format!("`{}` must be a boolean", self.name)),
+            (Default::Number(_), _) => return Err(format!("`{}` must be a number", self.name)),
+            _ => return Err(format!("`{}` must be a string", self.name)),
+        };
+        self.check(&value)?;
+        Ok(value)
+    }
+
+    // Rejects values that are of the right type but still make no sense.
+    pub fn check(&self, value: &OptionValue) -> Result<(), String> {
+        match (self.name, value) {
+            ("shiftwidth" | "tabstop" | "numberwidth", OptionValue::Number(n)) if *n < 1 => {
+                Err(format!("Argument must be positive: {}={}", self.name, n))
+            }
+            (_, OptionValue::Number(n)) if *n < 0 => {
+                Err(format!("Argument must be positive: {}={}", self.name, n))
+            }
+            ("backupext", OptionValue::String(ext)) if ext.is_empty() || ext.contains('/') => {
+                Err(format!("Invalid argument: {}={}", self.name, ext))
+            }
+            ("fileencoding", OptionValue::String(fenc)) if Encoding::parse(fenc).is_none() => {
+                Err(format!("Invalid argument: {}={}", self.name, fenc))
+            }
+            ("fileformat", OptionValue::String(ff))
+                if !matches!(ff.as_str(), "unix" | "dos" | "mac") =>
+            {
+                Err(format!("Invalid argument: {}={}", self.name, ff))
+            }
+            ("signcolumn", OptionValue::String(scl))
+                if !matches!(scl.as_str(), "auto" | "yes" | "no") =>
+            {
+                Err(format!("Invalid argument: {}={}", self.name, scl))
+            }
+            ("guicursor", OptionValue::List(items)) => items
+                .iter()
+                .try_for_each(|item| parse_cursor_item(item).map(|_| ())),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CursorShape {
+    Block,
+    Bar,
+    Underline,
+}
+
+// Parses an item of `guicursor` like `n-c:block` or `i:ver25-blinkon500` into
+// its modes, shape and whether the cursor blinks.
+fn parse_cursor_item(item: &str) -> Result<(Vec<&str>, CursorShape, bool), String> {
+    let invalid = || format!("Invalid argument: guicursor={}", item);
+    let (modes, args) = item.split_once(':').ok_or_else(invalid)?;
+    let mut shape = None;
+    let mut blink = false;
+    for arg in args.split('-') {
+        if arg == "block" {
+            shape = Some(CursorShape::Block);
+        } else if arg
+            .strip_prefix("ver")
+            .is_some_and(|n| n.parse::<u8>().is_ok())
+        {
+            shape = Some(CursorShape::Bar);
+        } else if arg
+            .strip_prefix("hor")
+            .is_some_and(|n| n.parse::<u8>().is_ok())
+        {
+            shape = Some(CursorShape::Underline);
+        } else if let Some(n) = arg.strip_prefix("blinkon") {
+            blink = n.parse::<u32>().map_err(|_| invalid())? > 0;
+        } else if !arg.starts_with("blinkwait") && !arg.starts_with("blinkoff") {
+            return Err(invalid());
+        }
+    }
+    Ok((
+        modes.split('-').collect(),
+        shape.ok_or_else(invalid)?,
+        blink,
+    ))
+}
+
+// The shape of the cursor in `mode` according to `guicursor`, and whether it
+// blinks. The last item that mentions the mode wins.
+pub fn cursor_shape(guicursor: &[String], mode: &str) -> Option<(CursorShape, bool)> {
+    guicursor
+        .iter()
+        .filter_map(|item| parse_cursor_item(item).ok())
+        .filter(|(modes, _, _)| modes.contains(&mode) || modes.contains(&"a"))
+        .map(|(_, shape, blink)| (shape, blink))
+        .next_back()
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionValue::Bool(b) => write!(f, "{}", b),
+            OptionValue::Number(n) => write!(f, "{}", n),
+            OptionValue::String(s) => write!(f, "{}", s),
+            OptionValue::List(items) => write!(f, "{}", items.join(",")),
+        }
+    }
+}
+
+// How `:set` shows an option: `name=value`, or `name`/`noname` for flags.
+pub fn show(def: &OptionDef, value: &OptionValue) -> String {
+    match value {
+        OptionValue::Bool(true) => def.name.to_string(),
+        OptionValue::Bool(false) => format!("no{}", def.name),
+        value => format!("{}={}", def.name, value),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Operator {
+    Assign,
+    Add,
+    Subtract,
+    Prepend,
+}
+
+// Combines the current value with the one given to `:set` using `op`, as in
+// `:set sw+=2` or `:set gcr-=i:ver25`.
+pub fn combine(
+    def: &OptionDef,
+    current: &OptionValue,
+    op: Operator,
+    value: OptionValue,
+) -> Result<OptionValue, String> {
+    let invalid = || format!("Invalid argument: {}", def.name);
+    Ok(match (op, current, value) {
+        (Operator::Assign, _, value) => value,
+        (Operator::Add, OptionValue::Number(a), OptionValue::Number(b)) => {
+            OptionValue::Number(a.checked_add(b).ok_or_else(invalid)?)
+        }
+        (Operator::Subtract, OptionValue::Number(a), OptionValue::Number(b)) => {
+            OptionValue::Number(a.checked_sub(b).ok_or_else(invalid)?)
+        }
+        (Operator::Prepend, OptionValue::Number(a), OptionValue::Number(b)) => {
+            OptionValue::Number(a.checked_mul(b).ok_or_else(invalid)?)
+        }
+        (Operator::Add, OptionValue::String(a), OptionValue::String(b)) => {
+            OptionValue::String(a.clone() + &b)
+        }
+        (Operator::Prepend, OptionValue::String(a), OptionValue::String(b)) => {
+            OptionValue::String(b + a)
+        }
+        (Operator::Subtract, OptionValue::String(a), OptionValue::String(b)) => {
+            OptionValue::String(a.replacen(&b, "", 1))
+        }
+        (Operator::Add, OptionValue::List(a), OptionValue::List(b)) => {
+            let mut list = a.clone();
+            list.extend(b.into_iter().filter(|item| !a.contains(item)));
+            OptionValue::List(list)
+        }
+        (Operator::Prepend, OptionValue::List(a), OptionValue::List(b)) => {
+            let mut list: Vec<String> = b;
+            list.extend(
+                a.iter()
+                    .filter(|item| !list.contains(item))
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            );
+            OptionValue::List(list)
+        }
+        (Operator::Subtract, OptionValue::List(a), OptionValue::List(b)) => {
+            OptionValue::List(a.iter().filter(|item| !b.contains(item)).cloned().collect())
+        }
+        _ => return Err(invalid()),
+    })
+}
+
+pub enum SetArg {
+    Show(&'static OptionDef),
+    Enable(&'static OptionDef),
+    Disable(&'static OptionDef),
+    Toggle(&'static OptionDef),
+    Reset(&'static OptionDef),
+    Modify(&'static OptionDef, Operator, String),
+}
+
+// Parses a single argument of `:set`.
+pub fn parse_arg(arg: &str) -> Result<SetArg, String> {
+    let unknown = |name: &str| format!("Unknown option: {}", name);
+
+    if let Some(i) = arg.find(['=', ':']) {
+        let (mut name, value) = (&arg[..i], &arg[i + 1..]);
+        let mut op = Operator::Assign;
+        for (suffix, operator) in [
+            ('+', Operator::Add),
+            ('-', Operator::Subtract),
+            ('^', Operator::Prepend),
+        ] {
+            if let Some(stripped) = name.strip_suffix(suffix) {
+                name = stripped;
+                op = operator;
+                break;
+            }
+        }
+        let def = find(name).ok_or_else(|| unknown(name))?;
+        return Ok(SetArg::Modify(def, op, value.to_string()));
+    }
+
+    if let Some(name) = arg.strip_suffix('?') {
+        return find(name).map(SetArg::Show).ok_or_else(|| unknown(name));
+    }
+    if let Some(name) = arg.strip_suffix('&') {
+        return find(name).map(SetArg::Reset).ok_or_else(|| unknown(name));
+    }
+    if let Some(name) = arg.strip_suffix('!') {
+        return find(name).map(SetArg::Toggle).ok_or_else(|| unknown(name));
+    }
+    if let Some(def) = find(arg) {
+        return Ok(match def.default {
+            Default::Bool(_) => SetArg::Enable(def),
+            _ => SetArg::Show(def),
+        });
+    }
+    if let Some(def) = arg.strip_prefix("no").and_then(find) {
+        return Ok(SetArg::Disable(def));
+    }
+    if let Some(def) = arg.strip_prefix("inv").and_then(find) {
+        return Ok(SetArg::Toggle(def));
+    }
+    Err(unknown(arg))
+}
+
+// Splits the arguments of `:set` on whitespace, except where it is escaped
+// with a backslash.
+pub fn split_args(args: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut chars = args.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => current.extend(chars.next()),
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    result.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        result.push(current);
+    }
+    result
+}
+
+// Values of options, keyed by their full name. The global set holds a value
+// for every option, while the sets kept by buffers and windows only hold the
+// options that were set locally.
+#[derive(Clone, Default)]
+pub struct Options {
+    values: HashMap<&'static str, OptionValue>,
+}
+
+impl Options {
+    pub fn defaults() -> Options {
+        Options {
+            values: OPTIONS
+                .iter()
+                .map(|def| (def.name, def.default_value()))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OptionValue> {
+        self.values.get(name)
+    }
+
+    pub fn set(&mut self, def: &'static OptionDef, value: OptionValue) {
+        self.values.insert(def.name, value);
+    }
+}
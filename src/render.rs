@@ -0,0 +1,391 @@
+use crate::buffer::EditorFile;
+use crate::editor::{Editor, EditorMode};
+use crate::highlight::{Span, TokenClass};
+use crate::key;
+use crate::options::{OptionValue, Options};
+use crate::screen::{self, Frame, Grid};
+use crate::window::{Rect, Window};
+use std::cmp::Ordering;
+use std::ops::Range;
+use std::path::Path;
+
+impl Editor {
+    fn line_spans(&mut self, buffer: usize, rows: Range<usize>) -> Vec<Vec<Span>> {
+        let file = self.buffer_mut(buffer);
+        match &mut file.highlighter {
+            Some(highlighter) => highlighter.highlight(&file.lines, rows),
+            None => rows.map(|_| Vec::new()).collect(),
+        }
+    }
+
+    // Draws the display columns `cols` of a line of a window at `x`, `y` on
+    // the grid, colored by `spans`.
+    fn draw_line(
+        &self,
+        grid: &mut Grid,
+        window: &Window,
+        (x, y): (usize, usize),
+        row: usize,
+        cols: Range<usize>,
+        spans: &[Span],
+    ) {
+        let file = self.buffer(window.buffer);
+        let tabstop = match self.buffer_option(file, "tabstop") {
+            OptionValue::Number(n) => *n as usize,
+            _ => 8,
+        };
+        let Range {
+            start: left,
+            end: right,
+        } = cols;
+        let mut spans = spans.iter().peekable();
+        let mut col = 0;
+        for (i, c) in file.lines[row].chars.char_indices() {
+            if col >= right {
+                break;
+            }
+            let width = screen::char_width(c, col, tabstop);
+            if col + width <= left {
+                col += width;
+                continue;
+            }
+            while spans.next_if(|span| span.end <= i).is_some() {}
+            let class = match spans.peek() {
+                Some(span) if span.start <= i => span.class,
+                _ => TokenClass::Normal,
+            };
+            let style = self.theme.text_style(class.group());
+            if col >= left && col + width <= right {
+                grid.put_char(x + col - left, y, c, col, tabstop, style);
+            } else {
+                // Only part of a wide character or tab is on the screen.
+                let start = col.max(left);
+                grid.fill(x + start - left, y, (col + width).min(right) - start, style);
+            }
+            col += width;
+        }
+    }
+
+    // The widths of the sign column and of the line numbers with the space
+    // after them, for a window with `options` on `file`.
+    pub(crate) fn gutter(&self, options: &Options, file: &EditorFile) -> (usize, usize) {
+        let flag = |name| matches!(self.window_option(options, name), OptionValue::Bool(true));
+        let signs = match self.window_option(options, "signcolumn") {
+            OptionValue::String(scl) if scl == "yes" => 2,
+            OptionValue::String(scl) if scl == "auto" => {
+                2 * usize::from(file.lines.iter().any(|line| line.sign.is_some()))
+            }
+            _ => 0,
+        };
+        let numbers = match flag("number") || flag("relativenumber") {
+            true => {
+                let width = match self.window_option(options, "numberwidth") {
+                    OptionValue::Number(n) => *n as usize,
+                    _ => 4,
+                };
+                file.lines.len().to_string().len().max(width - 1) + 1
+            }
+            false => 0,
+        };
+        (signs, numbers)
+    }
+
+    // Draws the sign and the number of a line left of its text. With both
+    // `number` and `relativenumber`, the line of the cursor has its own
+    // number and the others their distance to it.
+    fn draw_gutter(
+        &self,
+        grid: &mut Grid,
+        window: &Window,
+        rect: Rect,
+        y: usize,
+        row: usize,
+        (signs, numbers): (usize, usize),
+    ) {
+        let file = self.buffer(window.buffer);
+        let right = rect.x + rect.width;
+        if signs > 0 {
+            grid.fill(
+                rect.x,
+                y,
+                signs.min(rect.width),
+                self.theme.text_style("SignColumn"),
+            );
+            if let Some(sign) = &file.lines[row].sign {
+                let style = self.theme.text_style(&sign.group);
+                grid.put_str_within(rect.x, y, right.min(rect.x + signs), &sign.text, style);
+            }
+        }
+        if numbers > 0 {
+            let flag = |name| {
+                matches!(
+                    self.window_option(&window.options, name),
+                    OptionValue::Bool(true)
+                )
+            };
+            let relative = flag("relativenumber");
+            let cursor = row == window.row_pos;
+            let width = numbers - 1;
+            let text = match (flag("number"), relative) {
+                (true, true) if cursor => format!("{:<width$} ", row + 1),
+                (_, true) => format!("{:>width$} ", row.abs_diff(window.row_pos)),
+                _ => format!("{:>width$} ", row + 1),
+            };
+            let group = match relative && cursor {
+                true => "CursorLineNr",
+                false => "LineNr",
+            };
+            let style = self.theme.text_style(group);
+            grid.put_str_within(rect.x + signs, y, right, &text, style);
+        }
+    }
+
+    // Draws a window in `rect`, with a status line at the bottom if `status`
+    // is set.
+    fn draw_window(&mut self, grid: &mut Grid, window: &Window, rect: Rect, status: bool) {
+        let text_rows = rect.height.saturating_sub(usize::from(status));
+        let last = self.buffer(window.buffer).lines.len();
+        // The buffer may have changed in another window since.
+        let top = window.row_scroll_pos.min(last - 1);
+        let bottom = (top + text_rows).min(last);
+        let spans = self.line_spans(window.buffer, top..bottom);
+        let file = self.buffer(window.buffer);
+        let gutter = self.gutter(&window.options, file);
+        let x = rect.x + gutter.0 + gutter.1;
+        let width = rect.width.saturating_sub(gutter.0 + gutter.1);
+        let non_text = self.theme.text_style("NonText");
+        let right = rect.x + rect.width;
+        let mut y = 0;
+        match self.wrap(&window.options, file, width) {
+            None => {
+                let left = window.col_scroll_pos;
+                for (row, spans) in (top..bottom).zip(&spans) {
+                    self.draw_gutter(grid, window, rect, rect.y + y, row, gutter);
+                    let cols = left..left + width;
+                    self.draw_line(grid, window, (x, rect.y + y), row, cols, spans);
+                    y += 1;
+                }
+            }
+            Some(wrap) => {
+                let showbreak = screen::display_width(&wrap.showbreak, wrap.tabstop);
+                for (row, spans) in (top..bottom).zip(&spans) {
+                    let segments = wrap.segments(&file.lines[row].chars);
+                    // A line that does not fit below the others is left out
+                    // and its rows are marked with `@`.
+                    if y + segments.len() > text_rows && row > top {
+                        for y in y..text_rows {
+                            grid.put_str_within(rect.x, rect.y + y, right, "@", non_text);
+                        }
+                        y = text_rows;
+                        break;
+                    }
+                    // Rows of a top line that does not fit may be scrolled
+                    // out of view.
+                    let skip = match row == top && segments.len() > text_rows {
+                        true => window.col_scroll_pos.min(segments.len() - text_rows),
+                        false => 0,
+                    };
+                    for (i, segment) in segments.into_iter().enumerate().skip(skip) {
+                        if y == text_rows {
+                            break;
+                        }
+                        if i == 0 {
+                            self.draw_gutter(grid, window, rect, rect.y + y, row, gutter);
+                        } else if segment.indent >= showbreak && showbreak > 0 {
+                            let start = x + segment.indent - showbreak;
+                            grid.put_str_within(
+                                start,
+                                rect.y + y,
+                                right,
+                                &wrap.showbreak,
+                                non_text,
+                            );
+                        }
+                        let position = (x + segment.indent, rect.y + y);
+                        self.draw_line(grid, window, position, row, segment.cols, spans);
+                        y += 1;
+                    }
+                }
+            }
+        }
+        for y in y..text_rows {
+            grid.put_str_within(rect.x, rect.y + y, right, "~", non_text);
+        }
+        if status {
+            let group = match window.id == self.window {
+                true => "StatusLine",
+                false => "StatusLineNC",
+            };
+            let style = self.theme.style(group);
+            let y = rect.y + text_rows;
+            let text = self.buffer_status(self.buffer(window.buffer));
+            // The status line goes on under the separator at the right.
+            let width = (rect.width + 1).min(self.screen_cols - rect.x);
+            grid.fill(rect.x, y, width, style);
+            grid.put_str_within(rect.x, y, rect.x + width, &text, style);
+        }
+    }
+
+    // Builds the picture of the screen from the state of the editor.
+    pub fn draw(&mut self) -> Frame {
+        let area = self.window_area();
+        let mut grid = Grid::new(
+            self.screen_cols,
+            area.y + area.height + 1,
+            self.theme.text_style("Normal"),
+        );
+        let (rects, separators) = self.window_rects();
+        let single = rects.len() == 1;
+        let separator = self.theme.style("VertSplit");
+        for rect in separators {
+            for y in rect.y..rect.y + rect.height {
+                grid.put_str(rect.x, y, "|", separator);
+            }
+        }
+        for (id, rect) in &rects {
+            let window = match *id == self.window {
+                true => self.current_window(),
+                false => self.windows.iter().find(|w| w.id == *id).unwrap().clone(),
+            };
+            self.draw_window(&mut grid, &window, *rect, !single);
+        }
+        self.draw_tabline(&mut grid);
+        self.draw_status_bar(&mut grid, single);
+
+        let rect = rects.iter().find(|(id, _)| *id == self.window).unwrap().1;
+        let cursor = match self.mode {
+            EditorMode::Command => {
+                let col = 1 + screen::display_width(&self.command, 8);
+                (
+                    col.min(self.screen_cols.saturating_sub(1)),
+                    area.y + area.height,
+                )
+            }
+            _ => {
+                let (x, y) = self.cursor_position();
+                (rect.x + x, rect.y + y)
+            }
+        };
+        Frame {
+            grid,
+            cursor,
+            cursor_shape: self.cursor_shape(),
+        }
+    }
+
+    // The keys of the command being typed, which `showcmd` shows at the
+    // right of the status bar.
+    pub fn showcmd(&self) -> String {
+        let mut keys = self.pending.map(String::from).unwrap_or_default();
+        keys += &key::to_notation(&self.keys);
+        keys += &key::to_notation(&self.typed);
+        keys
+    }
+
+    // What the last line shows while a command is typed or after one ran.
+    fn command_line(&self) -> Option<String> {
+        match (&self.mode, &self.message) {
+            (EditorMode::Command, _) => Some(format!(":{}", self.command)),
+            (_, Some(message)) => Some(message.clone()),
+            (_, None) => None,
+        }
+    }
+
+    // What the status line of a window on `file` shows.
+    fn buffer_status(&self, file: &EditorFile) -> String {
+        let mut status = file.display_name().to_string();
+        if self.buffer_modified(file) {
+            status += " [+]";
+        }
+        if matches!(
+            self.buffer_option(file, "readonly"),
+            OptionValue::Bool(true)
+        ) {
+            status += " [RO]";
+        }
+        status
+    }
+
+    // The label of a tab page in the tabline: the number of windows if there
+    // are several, `+` if a buffer in one of them was modified, and the name
+    // of the buffer in its current window.
+    fn tab_label(&self, windows: &[Window], current: usize) -> String {
+        let mut label = " ".to_string();
+        if windows.len() > 1 {
+            label += &windows.len().to_string();
+        }
+        if windows
+            .iter()
+            .any(|window| self.buffer_modified(self.buffer(window.buffer)))
+        {
+            label += "+";
+        }
+        if label.len() > 1 {
+            label += " ";
+        }
+        let window = windows.iter().find(|window| window.id == current).unwrap();
+        let name = self.buffer(window.buffer).display_name();
+        label += Path::new(name)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(name);
+        label + " "
+    }
+
+    fn draw_tabline(&self, grid: &mut Grid) {
+        if self.tabline_rows() == 0 {
+            return;
+        }
+        grid.fill(0, 0, self.screen_cols, self.theme.style("TabLineFill"));
+        let mut current: Vec<_> = self.windows.clone();
+        current.push(self.current_window());
+        let mut x = 0;
+        for index in 0..self.tab_count() {
+            let (label, group) = match index.cmp(&self.tab) {
+                Ordering::Equal => (self.tab_label(&current, self.window), "TabLineSel"),
+                Ordering::Less => {
+                    let tab = &self.tabs[index];
+                    (self.tab_label(&tab.windows, tab.window), "TabLine")
+                }
+                Ordering::Greater => {
+                    let tab = &self.tabs[index - 1];
+                    (self.tab_label(&tab.windows, tab.window), "TabLine")
+                }
+            };
+            x = grid.put_str(x, 0, &label, self.theme.style(group));
+        }
+    }
+
+    // What the status bar shows when nothing else is going on. With a single
+    // window, the last line is its status line as well.
+    pub fn status(&self) -> String {
+        self.command_line()
+            .unwrap_or_else(|| self.buffer_status(&self.file))
+    }
+
+    fn draw_status_bar(&self, grid: &mut Grid, single: bool) {
+        let (text, style) = match single {
+            true => (self.status(), self.theme.style("StatusLine")),
+            false => (
+                self.command_line().unwrap_or_default(),
+                self.theme.text_style("Normal"),
+            ),
+        };
+        let area = self.window_area();
+        let y = area.y + area.height;
+        let width = self.screen_cols;
+        grid.fill(0, y, width, style);
+        grid.put_str(0, y, &text, style);
+        let showcmd =
+            self.flag("showcmd") && !matches!(self.mode, EditorMode::Command) && width > 11;
+        if showcmd {
+            let keys = self.showcmd();
+            let keys: String = keys
+                .chars()
+                .skip(keys.chars().count().saturating_sub(10))
+                .collect();
+            grid.fill(width - 11, y, 11, style);
+            grid.put_str(width - 10, y, &keys, style);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{fchown, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::process;
+
+// Replaces the contents of `path` without ever leaving it half written: the
+// contents go to a temporary file next to it, which is synced and then
+// renamed over the original. The permissions, owner and extended attributes
+// of the original are kept. With `backup`, the original is kept as a file
+// with that extension. A read-only original is only replaced with `force`.
+pub fn write_atomic(
+    path: &Path,
+    contents: &[u8],
+    backup: Option<&str>,
+    force: bool,
+) -> io::Result<()> {
+    // Write through symlinks instead of replacing them.
+    let path = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(err) => return Err(err),
+    };
+    let original = match fs::metadata(&path) {
+        Ok(metadata) => Some(metadata),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+    if !force
+        && original
+            .as_ref()
+            .is_some_and(|metadata| metadata.permissions().readonly())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "file is read-only",
+        ));
+    }
+
+    let temp = with_suffix(&path, &format!(".{}.tmp", process::id()), true);
+    let result = write_temp(&temp, &path, contents, original.as_ref()).and_then(|()| {
+        if let (Some(ext), Some(_)) = (backup, &original) {
+            make_backup(&path, &with_suffix(&path, ext, false))?;
+        }
+        fs::rename(&temp, &path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+        return result;
+    }
+    sync_dir(&path)
+}
+
+fn write_temp(
+    temp: &Path,
+    path: &Path,
+    contents: &[u8],
+    original: Option<&fs::Metadata>,
+) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+    file.write_all(contents)?;
+    if let Some(original) = original {
+        file.set_permissions(original.permissions())?;
+        // Only root can give a file away, so keeping the owner is best effort.
+        let _ = fchown(&file, Some(original.uid()), Some(original.gid()));
+        copy_xattrs(path, &file);
+    }
+    file.sync_all()
+}
+
+// Whether `a` and `b` name the same file, such as `f` and `./f`, or a file
+// and a link to it.
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+// Appends to `path`, which cannot be done atomically.
+pub fn append(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+// Keeps the original as `backup`. A hard link is enough, since the original
+// is replaced by a rename and not overwritten.
+fn make_backup(path: &Path, backup: &Path) -> io::Result<()> {
+    match fs::remove_file(backup) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    if fs::hard_link(path, backup).is_err() {
+        fs::copy(path, backup)?;
+    }
+    Ok(())
+}
+
+// `path` with `suffix` added to its file name, hidden with a leading dot if
+// `hidden` is set.
+fn with_suffix(path: &Path, suffix: &str, hidden: bool) -> PathBuf {
+    let mut name = OsString::new();
+    if hidden {
+        name.push(".");
+    }
+    name.push(path.file_name().unwrap_or_default());
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+// Makes the rename itself durable.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+// Copies the extended attributes of `from` to `to`, as far as we are allowed
+// to set them.
+#[cfg(target_os = "linux")]
+fn copy_xattrs(from: &Path, to: &File) {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr;
+
+    let Ok(from) = CString::new(from.as_os_str().as_bytes()) else {
+        return;
+    };
+    let size = unsafe { libc::listxattr(from.as_ptr(), ptr::null_mut(), 0) };
+    if size <= 0 {
+        return;
+    }
+    let mut names = vec![0u8; size as usize];
+    let size = unsafe { libc::listxattr(from.as_ptr(), names.as_mut_ptr().cast(), names.len()) };
+    if size <= 0 {
+        return;
+    }
+    names.truncate(size as usize);
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let Ok(name) = CString::new(name) else {
+            continue;
+        };
+        let size = unsafe { libc::getxattr(from.as_ptr(), name.as_ptr(), ptr::null_mut(), 0) };
+        if size < 0 {
+            continue;
+        }
+        let mut value = vec![0u8; size as usize];
+        let size = unsafe {
+            libc::getxattr(
+                from.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if size < 0 {
+            continue;
+        }
+        unsafe {
+            libc::fsetxattr(
+                to.as_raw_fd(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                size as usize,
+                0,
+            )
+        };
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_xattrs(_from: &Path, _to: &File) {}
@@ -0,0 +1,263 @@
+use crate::options::CursorShape;
+use crossterm::style::{Attribute, ContentStyle, Print, SetAttribute, SetStyle};
+use crossterm::{cursor, queue, terminal};
+use std::io::{self, Write};
+use std::ops::Range;
+use unicode_width::UnicodeWidthChar;
+
+// The right half of a character that takes up two cells.
+const WIDE_CONTINUATION: char = '\0';
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Cell {
+    pub symbol: char,
+    pub style: ContentStyle,
+}
+
+impl Cell {
+    pub fn blank(style: ContentStyle) -> Cell {
+        Cell { symbol: ' ', style }
+    }
+}
+
+// The contents of the terminal as a grid of styled cells.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize, style: ContentStyle) -> Grid {
+        Grid {
+            width,
+            height,
+            cells: vec![Cell::blank(style); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Cell {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x] = cell;
+        }
+    }
+
+    pub fn fill(&mut self, x: usize, y: usize, width: usize, style: ContentStyle) {
+        for x in x..(x + width).min(self.width) {
+            self.set(x, y, Cell::blank(style));
+        }
+    }
+
+    // Writes `text` starting at `x`, cut off at the end of the row. Returns
+    // the column after the text.
+    pub fn put_str(&mut self, x: usize, y: usize, text: &str, style: ContentStyle) -> usize {
+        self.put_str_within(x, y, self.width, text, style)
+    }
+
+    // Like `put_str`, but cut off at column `right`.
+    pub fn put_str_within(
+        &mut self,
+        x: usize,
+        y: usize,
+        right: usize,
+        text: &str,
+        style: ContentStyle,
+    ) -> usize {
+        let mut x = x;
+        for c in text.chars() {
+            let width = char_width(c, x, 8);
+            if x + width > right.min(self.width) {
+                break;
+            }
+            self.put_char(x, y, c, x, 8, style);
+            x += width;
+        }
+        x
+    }
+
+    // Draws `c` as it would appear at display column `col` of a line, at
+    // `x`. Tabs become spaces, control characters are shown like `^X` and
+    // wide characters take two cells.
+    pub fn put_char(
+        &mut self,
+        x: usize,
+        y: usize,
+        c: char,
+        col: usize,
+        tabstop: usize,
+        style: ContentStyle,
+    ) {
+        for (i, symbol) in char_cells(c, col, tabstop).into_iter().enumerate() {
+            self.set(x + i, y, Cell { symbol, style });
+        }
+    }
+
+    // The text of row `y`, without trailing blanks.
+    pub fn row_text(&self, y: usize) -> String {
+        let text: String = (0..self.width)
+            .map(|x| self.get(x, y).symbol)
+            .filter(|&symbol| symbol != WIDE_CONTINUATION)
+            .collect();
+        text.trim_end().to_string()
+    }
+}
+
+// The symbols of the cells that `c` takes up at display column `col`.
+fn char_cells(c: char, col: usize, tabstop: usize) -> Vec<char> {
+    match c {
+        '\t' => vec![' '; char_width(c, col, tabstop)],
+        c if c.is_ascii_control() => vec!['^', ((c as u8) ^ 0x40) as char],
+        c if c.is_control() => vec!['?'],
+        c if char_width(c, col, tabstop) == 2 => vec![c, WIDE_CONTINUATION],
+        c => vec![c],
+    }
+}
+
+// How many columns `c` takes up when it starts at display column `col`.
+pub fn char_width(c: char, col: usize, tabstop: usize) -> usize {
+    match c {
+        '\t' => tabstop.max(1) - col % tabstop.max(1),
+        c if c.is_ascii_control() => 2,
+        c => c.width().unwrap_or(1).max(1),
+    }
+}
+
+// How many columns `text` takes up.
+pub fn display_width(text: &str, tabstop: usize) -> usize {
+    text.chars()
+        .fold(0, |col, c| col + char_width(c, col, tabstop))
+}
+
+// A complete picture of the screen: the grid and where the cursor is.
+pub struct Frame {
+    pub grid: Grid,
+    pub cursor: (usize, usize),
+    pub cursor_shape: (CursorShape, bool),
+}
+
+// Draws frames to the terminal. Only the cells that differ from the previous
+// frame are written, in a single flush.
+#[derive(Default)]
+pub struct Screen {
+    previous: Option<Grid>,
+    cursor_shape: Option<(CursorShape, bool)>,
+}
+
+impl Screen {
+    // Makes the next frame be drawn in full, e.g. after the terminal was
+    // resized and its contents can no longer be trusted.
+    pub fn invalidate(&mut self) {
+        self.previous = None;
+    }
+
+    pub fn draw(&mut self, out: &mut impl Write, frame: Frame) -> io::Result<()> {
+        let Frame {
+            grid,
+            cursor: (x, y),
+            cursor_shape,
+        } = frame;
+        queue!(out, cursor::Hide)?;
+        let previous = self
+            .previous
+            .take()
+            .filter(|previous| previous.width == grid.width && previous.height == grid.height);
+        if previous.is_none() {
+            queue!(out, terminal::Clear(terminal::ClearType::All))?;
+        }
+        for (y, run) in diff(previous.as_ref(), &grid) {
+            draw_run(out, &grid, y, run.start, run.end)?;
+        }
+        if self.cursor_shape != Some(cursor_shape) {
+            queue!(out, cursor_style(cursor_shape))?;
+            self.cursor_shape = Some(cursor_shape);
+        }
+        queue!(out, cursor::MoveTo(x as u16, y as u16), cursor::Show)?;
+        out.flush()?;
+        self.previous = Some(grid);
+        Ok(())
+    }
+}
+
+// The runs of cells that have to be written to turn `previous` into `next`,
+// by row. Without a previous grid of the same size the screen is cleared
+// first, so only cells that are not blank are written.
+pub fn diff(previous: Option<&Grid>, next: &Grid) -> Vec<(usize, Range<usize>)> {
+    let cleared;
+    let previous = match previous {
+        Some(previous) if previous.width == next.width && previous.height == next.height => {
+            previous
+        }
+        _ => {
+            cleared = Grid::new(next.width, next.height, ContentStyle::new());
+            &cleared
+        }
+    };
+    (0..next.height)
+        .flat_map(|y| {
+            changed_runs(previous, next, y)
+                .into_iter()
+                .map(move |(start, end)| (y, start..end))
+        })
+        .collect()
+}
+
+// The ranges of cells in row `y` that changed. The left half of a wide
+// character is included whenever its right half changed.
+fn changed_runs(previous: &Grid, next: &Grid, y: usize) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for x in 0..next.width {
+        if previous.get(x, y) == next.get(x, y) {
+            continue;
+        }
+        let mut start = x;
+        while start > 0 && next.get(start, y).symbol == WIDE_CONTINUATION {
+            start -= 1;
+        }
+        match runs.last_mut() {
+            Some((_, end)) if *end >= start => *end = x + 1,
+            _ => runs.push((start, x + 1)),
+        }
+    }
+    runs
+}
+
+fn draw_run(
+    out: &mut impl Write,
+    grid: &Grid,
+    y: usize,
+    start: usize,
+    end: usize,
+) -> io::Result<()> {
+    queue!(out, cursor::MoveTo(start as u16, y as u16))?;
+    let mut style = None;
+    let mut text = String::new();
+    for x in start..end {
+        let cell = grid.get(x, y);
+        if cell.symbol == WIDE_CONTINUATION {
+            continue;
+        }
+        if style != Some(cell.style) {
+            queue!(out, Print(std::mem::take(&mut text)))?;
+            queue!(out, SetAttribute(Attribute::Reset), SetStyle(cell.style))?;
+            style = Some(cell.style);
+        }
+        text.push(cell.symbol);
+    }
+    queue!(out, Print(text), SetAttribute(Attribute::Reset))
+}
+
+fn cursor_style(shape: (CursorShape, bool)) -> cursor::SetCursorStyle {
+    match shape {
+        (CursorShape::Block, false) => cursor::SetCursorStyle::SteadyBlock,
+        (CursorShape::Block, true) => cursor::SetCursorStyle::BlinkingBlock,
+        (CursorShape::Bar, false) => cursor::SetCursorStyle::SteadyBar,
+        (CursorShape::Bar, true) => cursor::SetCursorStyle::BlinkingBar,
+        (CursorShape::Underline, false) => cursor::SetCursorStyle::SteadyUnderScore,
+        (CursorShape::Underline, true) => cursor::SetCursorStyle::BlinkingUnderScore,
+    }
+}
@@ -0,0 +1,146 @@
+use crate::editor::{Editor, Effect};
+use crate::window::{Layout, Window};
+use std::mem;
+
+// A set of windows that are shown together. The current tab page keeps its
+// windows in the editor, the others keep theirs here.
+pub struct TabPage {
+    pub layout: Layout,
+    // All windows of the tab page, and the id of the one that was current.
+    pub windows: Vec<Window>,
+    pub window: usize,
+}
+
+impl Editor {
+    // The tabline is only shown when there is more than one tab page.
+    pub(crate) fn tabline_rows(&self) -> usize {
+        usize::from(!self.tabs.is_empty())
+    }
+
+    // The number of tab pages, with the current one.
+    pub fn tab_count(&self) -> usize {
+        self.tabs.len() + 1
+    }
+
+    fn take_tab(&mut self) -> TabPage {
+        let mut windows = mem::take(&mut self.windows);
+        windows.push(self.current_window());
+        TabPage {
+            layout: mem::replace(&mut self.layout, Layout::Window(self.window)),
+            windows,
+            window: self.window,
+        }
+    }
+
+    fn load_tab(&mut self, mut tab: TabPage) {
+        let index = tab.windows.iter().position(|w| w.id == tab.window).unwrap();
+        let target = tab.windows.remove(index);
+        self.windows = tab.windows;
+        self.layout = tab.layout;
+        self.load_window(target);
+    }
+
+    // Lays out the windows of every tab page again when the tabline comes or
+    // goes.
+    fn tabs_changed(&mut self, had_tabline: bool) {
+        if had_tabline == self.tabs.is_empty() {
+            let area = self.window_area();
+            self.layout.equalize(area);
+            for tab in &mut self.tabs {
+                tab.layout.equalize(area);
+            }
+        }
+        self.fit_windows();
+    }
+
+    // Opens a tab page after the current one, with a single window on the
+    // current buffer.
+    pub fn new_tab(&mut self) {
+        let had_tabline = !self.tabs.is_empty();
+        let tab = self.take_tab();
+        self.tabs.insert(self.tab, tab);
+        self.tab += 1;
+        self.last_window += 1;
+        self.window = self.last_window;
+        self.layout = Layout::Window(self.window);
+        self.tabs_changed(had_tabline);
+    }
+
+    // Goes to the tab page at `index`, counting from 0.
+    pub fn goto_tab(&mut self, index: usize) {
+        if index == self.tab || index >= self.tab_count() {
+            return;
+        }
+        let tab = self.take_tab();
+        self.tabs.insert(self.tab, tab);
+        let tab = self.tabs.remove(index);
+        self.tab = index;
+        self.load_tab(tab);
+    }
+
+    // Closes the current tab page, keeping the buffers of its windows in the
+    // list. The tab page after it becomes current, or the one before it if
+    // it was the last.
+    pub fn close_tab(&mut self) -> Result<(), String> {
+        if self.tabs.is_empty() {
+            return Err("Cannot close last tab page".to_string());
+        }
+        let index = self.tab.min(self.tabs.len() - 1);
+        let tab = self.tabs.remove(index);
+        self.tab = index;
+        self.load_tab(tab);
+        self.tabs_changed(true);
+        Ok(())
+    }
+
+    // Closes all tab pages but the current one.
+    pub fn only_tab(&mut self) {
+        let had_tabline = !self.tabs.is_empty();
+        self.tabs.clear();
+        self.tab = 0;
+        self.tabs_changed(had_tabline);
+    }
+
+    // Closes the windows on buffer `number` in the other tab pages, and the
+    // tab pages that are left without windows.
+    pub(crate) fn close_tab_windows(&mut self, number: usize) {
+        let had_tabline = !self.tabs.is_empty();
+        let area = self.window_area();
+        let current = self.tab;
+        let mut index = 0;
+        self.tabs.retain_mut(|tab| {
+            let closed: Vec<_> = tab
+                .windows
+                .iter()
+                .filter(|window| window.buffer == number)
+                .map(|window| window.id)
+                .collect();
+            tab.windows.retain(|window| window.buffer != number);
+            for id in closed {
+                tab.layout.remove(id);
+            }
+            tab.layout.equalize(area);
+            if let Some(&id) = tab.layout.windows().first() {
+                if !tab.windows.iter().any(|w| w.id == tab.window) {
+                    tab.window = id;
+                }
+            }
+            index += 1;
+            if tab.windows.is_empty() && index <= current {
+                self.tab -= 1;
+            }
+            !tab.windows.is_empty()
+        });
+        self.tabs_changed(had_tabline);
+    }
+
+    // `gt` and `gT`, and the `:tabnext` and `:tabprevious` commands.
+    pub(crate) fn cycle_tab(&mut self, forward: bool) -> Result<Effect, String> {
+        let count = self.tab_count();
+        match forward {
+            true => self.goto_tab((self.tab + 1) % count),
+            false => self.goto_tab((self.tab + count - 1) % count),
+        }
+        Ok(Effect::Redraw)
+    }
+}
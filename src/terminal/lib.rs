@@ -8,7 +8,7 @@ use std::os::unix::io::AsRawFd;
 pub fn enable_raw_mode() -> Result<Termios, std::io::Error> {
     let fd = std::io::stdin().as_raw_fd();
     let mut termios = Termios::from_fd(fd)?;
-    let orig_termios = termios.clone();
+    let orig_termios = termios;
 
     termios.c_iflag &= !(BRKINT | ISTRIP | ICRNL | IXON | INPCK);
     termios.c_oflag &= !(OPOST);
@@ -26,3 +26,16 @@ pub fn restore_terminal(termios: Termios) -> Result<(), std::io::Error> {
     let fd = std::io::stdin().as_raw_fd();
     tcsetattr(fd, TCSAFLUSH, &termios)
 }
+
+// Makes the terminal standard input again after the file to edit was read
+// from it.
+pub fn reopen_stdin() -> Result<(), std::io::Error> {
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
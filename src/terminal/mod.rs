@@ -1 +1,178 @@
+use crate::screen::{self, Frame, Screen};
+use crossterm::event::{self, Event};
+use crossterm::{cursor, queue, terminal};
+use std::collections::VecDeque;
+use std::io::{self, stdout, Write};
+use std::ops::Range;
+use std::time::Duration;
+
 pub mod lib;
+
+// Where the editor draws its frames and reads its events from.
+pub trait Backend {
+    // The number of columns and rows.
+    fn size(&self) -> io::Result<(usize, usize)>;
+    fn enter(&mut self) -> io::Result<()>;
+    fn leave(&mut self) -> io::Result<()>;
+    fn draw(&mut self, frame: Frame) -> io::Result<()>;
+    // Returns true if an event can be read within `timeout`.
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool>;
+    fn read(&mut self) -> io::Result<Event>;
+}
+
+fn terminal_size() -> io::Result<(usize, usize)> {
+    let (cols, rows) = terminal::size()?;
+    Ok((cols as usize, rows as usize))
+}
+
+// Reads an event from the terminal. After a resize, the contents of the
+// terminal can no longer be trusted and the next frame is drawn in full.
+fn read_event(screen: &mut Screen) -> io::Result<Event> {
+    let event = event::read()?;
+    if let Event::Resize(..) = event {
+        screen.invalidate();
+    }
+    Ok(event)
+}
+
+// Leaves the terminal the way the editor found it, apart from raw mode.
+fn reset_screen() -> io::Result<()> {
+    let mut out = stdout().lock();
+    queue!(
+        out,
+        cursor::SetCursorStyle::DefaultUserShape,
+        cursor::MoveTo(0, 0),
+        terminal::Clear(terminal::ClearType::All),
+        cursor::Show,
+    )?;
+    out.flush()
+}
+
+#[derive(Default)]
+pub struct CrosstermBackend {
+    screen: Screen,
+}
+
+impl Backend for CrosstermBackend {
+    fn size(&self) -> io::Result<(usize, usize)> {
+        terminal_size()
+    }
+
+    fn enter(&mut self) -> io::Result<()> {
+        terminal::enable_raw_mode()
+    }
+
+    fn leave(&mut self) -> io::Result<()> {
+        reset_screen()?;
+        terminal::disable_raw_mode()
+    }
+
+    fn draw(&mut self, frame: Frame) -> io::Result<()> {
+        self.screen.draw(&mut stdout().lock(), frame)
+    }
+
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool> {
+        event::poll(timeout)
+    }
+
+    fn read(&mut self) -> io::Result<Event> {
+        read_event(&mut self.screen)
+    }
+}
+
+// Puts the terminal in raw mode with termios directly, instead of through
+// crossterm. Drawing and reading events are the same.
+#[derive(Default)]
+pub struct TermiosBackend {
+    screen: Screen,
+    original: Option<termios::Termios>,
+}
+
+impl Backend for TermiosBackend {
+    fn size(&self) -> io::Result<(usize, usize)> {
+        terminal_size()
+    }
+
+    fn enter(&mut self) -> io::Result<()> {
+        self.original = Some(lib::enable_raw_mode()?);
+        Ok(())
+    }
+
+    fn leave(&mut self) -> io::Result<()> {
+        reset_screen()?;
+        match self.original.take() {
+            Some(original) => lib::restore_terminal(original),
+            None => Ok(()),
+        }
+    }
+
+    fn draw(&mut self, frame: Frame) -> io::Result<()> {
+        self.screen.draw(&mut stdout().lock(), frame)
+    }
+
+    fn poll(&mut self, timeout: Duration) -> io::Result<bool> {
+        event::poll(timeout)
+    }
+
+    fn read(&mut self) -> io::Result<Event> {
+        read_event(&mut self.screen)
+    }
+}
+
+// A terminal that only exists in memory, for running the editor without a
+// TTY. Events are queued up front and the last frame drawn is kept, along
+// with the cells a terminal would have had to write for it.
+pub struct MemoryBackend {
+    pub width: usize,
+    pub height: usize,
+    pub raw: bool,
+    pub events: VecDeque<Event>,
+    pub frame: Option<Frame>,
+    pub updates: Vec<(usize, Range<usize>)>,
+}
+
+impl MemoryBackend {
+    pub fn new(width: usize, height: usize) -> MemoryBackend {
+        MemoryBackend {
+            width,
+            height,
+            raw: false,
+            events: VecDeque::new(),
+            frame: None,
+            updates: Vec::new(),
+        }
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn size(&self) -> io::Result<(usize, usize)> {
+        Ok((self.width, self.height))
+    }
+
+    fn enter(&mut self) -> io::Result<()> {
+        self.raw = true;
+        Ok(())
+    }
+
+    fn leave(&mut self) -> io::Result<()> {
+        self.raw = false;
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: Frame) -> io::Result<()> {
+        self.updates = screen::diff(self.frame.as_ref().map(|f| &f.grid), &frame.grid);
+        self.frame = Some(frame);
+        Ok(())
+    }
+
+    fn poll(&mut self, _timeout: Duration) -> io::Result<bool> {
+        Ok(!self.events.is_empty())
+    }
+
+    // Fails once all events were read, since none can arrive later.
+    fn read(&mut self) -> io::Result<Event> {
+        self.events
+            .pop_front()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "no more events"))
+    }
+}
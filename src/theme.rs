@@ -0,0 +1,239 @@
+use crossterm::style::{Attribute, Color, ContentStyle};
+use std::collections::HashMap;
+use std::path::Path;
+use std::{env, fs};
+use toml::{Table, Value};
+
+const BUILTIN_THEMES: [(&str, &str); 2] = [
+    ("default", include_str!("colors/default.toml")),
+    ("gruvbox", include_str!("colors/gruvbox.toml")),
+];
+
+// Groups that are not defined by a theme are drawn like the group they fall
+// back to, and ultimately like `Normal`.
+fn fallback(group: &str) -> Option<&'static str> {
+    Some(match group {
+        "Macro" => "PreProc",
+        "Label" => "Special",
+        "Code" => "String",
+        "CursorLineNr" => "LineNr",
+        "StatusLineNC" | "VertSplit" => "StatusLine",
+        "TabLine" | "TabLineFill" => "StatusLineNC",
+        "TabLineSel" => "Normal",
+        "SignColumn" => "LineNr",
+        _ => return None,
+    })
+}
+
+pub struct Theme {
+    pub name: String,
+    groups: HashMap<String, ContentStyle>,
+}
+
+// Whether the terminal claims to support 24-bit color. Otherwise themes use
+// their `ctermfg`/`ctermbg` values, or the closest 256-color match.
+fn truecolor() -> bool {
+    env::var("COLORTERM").is_ok_and(|v| v == "truecolor" || v == "24bit")
+}
+
+fn parse_hex(hex: &str) -> Option<(u8, u8, u8)> {
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+// Maps a color onto the xterm 256-color palette: the 6x6x6 color cube or the
+// grayscale ramp, whichever is closer.
+fn to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    const STEPS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let nearest = |v: u8| {
+        (0..6)
+            .min_by_key(|&i| (STEPS[i] as i32 - v as i32).abs())
+            .unwrap()
+    };
+    let (ri, gi, bi) = (nearest(r), nearest(g), nearest(b));
+    let cube = (STEPS[ri], STEPS[gi], STEPS[bi]);
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_index = (average.saturating_sub(8) / 10).min(23) as u8;
+    let gray = 8 + gray_index * 10;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        [(cr, r), (cg, g), (cb, b)]
+            .iter()
+            .map(|&(a, b)| (a as i32 - b as i32).pow(2))
+            .sum::<i32>()
+    };
+    if distance((gray, gray, gray)) < distance(cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+    }
+}
+
+fn parse_color(value: &Value, truecolor: bool) -> Result<Option<Color>, String> {
+    match value {
+        Value::Integer(n) if (0..=255).contains(n) => Ok(Some(Color::AnsiValue(*n as u8))),
+        Value::String(s) if s == "none" || s == "NONE" => Ok(None),
+        Value::String(s) if s.starts_with('#') => {
+            let (r, g, b) = parse_hex(&s[1..]).ok_or_else(|| format!("invalid color `{}`", s))?;
+            Ok(Some(match truecolor {
+                true => Color::Rgb { r, g, b },
+                false => Color::AnsiValue(to_ansi256(r, g, b)),
+            }))
+        }
+        Value::String(s) => Color::try_from(s.as_str())
+            .map(Some)
+            .map_err(|_| format!("unknown color `{}`", s)),
+        _ => Err(format!("invalid color `{}`", value)),
+    }
+}
+
+fn parse_attribute(name: &str) -> Result<Attribute, String> {
+    Ok(match name {
+        "bold" => Attribute::Bold,
+        "italic" => Attribute::Italic,
+        "underline" => Attribute::Underlined,
+        "undercurl" => Attribute::Undercurled,
+        "reverse" => Attribute::Reverse,
+        "dim" => Attribute::Dim,
+        "strikethrough" => Attribute::CrossedOut,
+        _ => return Err(format!("unknown attribute `{}`", name)),
+    })
+}
+
+fn parse_group(table: &Table, truecolor: bool) -> Result<ContentStyle, String> {
+    let mut style = ContentStyle::new();
+    for (key, value) in table {
+        match key.as_str() {
+            "fg" if truecolor || !table.contains_key("ctermfg") => {
+                style.foreground_color = parse_color(value, truecolor)?
+            }
+            "bg" if truecolor || !table.contains_key("ctermbg") => {
+                style.background_color = parse_color(value, truecolor)?
+            }
+            "ctermfg" if !truecolor => style.foreground_color = parse_color(value, false)?,
+            "ctermbg" if !truecolor => style.background_color = parse_color(value, false)?,
+            "fg" | "bg" | "ctermfg" | "ctermbg" | "link" => {}
+            "attrs" => {
+                let attrs = value
+                    .as_array()
+                    .ok_or("`attrs` must be a list of strings")?;
+                for attr in attrs {
+                    let attr = attr.as_str().ok_or("`attrs` must be a list of strings")?;
+                    style.attributes.set(parse_attribute(attr)?);
+                }
+            }
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+    }
+    Ok(style)
+}
+
+impl Theme {
+    // A theme is a TOML table per highlight group, e.g.
+    //
+    //     [Comment]
+    //     fg = "#928374"
+    //     ctermfg = 245
+    //     attrs = ["italic"]
+    //
+    // `link = "Group"` draws a group like another one.
+    pub fn from_toml(name: &str, source: &str) -> Result<Theme, String> {
+        Theme::parse(name, source, truecolor())
+    }
+
+    // Like `from_toml`, for a terminal with or without 24-bit color.
+    pub fn parse(name: &str, source: &str, truecolor: bool) -> Result<Theme, String> {
+        let table: Table = source
+            .parse()
+            .map_err(|err: toml::de::Error| err.to_string())?;
+        let mut groups = HashMap::new();
+        let mut links = Vec::new();
+        for (group, value) in &table {
+            let value = value
+                .as_table()
+                .ok_or_else(|| format!("`{}` must be a table", group))?;
+            if let Some(link) = value.get("link") {
+                let link = link
+                    .as_str()
+                    .ok_or_else(|| format!("{}: `link` must be a string", group))?;
+                links.push((group.clone(), link.to_string()));
+            } else {
+                let style =
+                    parse_group(value, truecolor).map_err(|err| format!("{}: {}", group, err))?;
+                groups.insert(group.clone(), style);
+            }
+        }
+        let mut theme = Theme {
+            name: name.to_string(),
+            groups,
+        };
+        // Links may point at other links, so resolve them until none are left
+        // whose target is still pending.
+        while !links.is_empty() {
+            let ready = links
+                .iter()
+                .position(|(_, link)| !links.iter().any(|(group, _)| group == link));
+            let Some(i) = ready else {
+                return Err(format!("{}: circular `link`", links[0].0));
+            };
+            let (group, link) = links.remove(i);
+            let style = theme.style(&link);
+            theme.groups.insert(group, style);
+        }
+        Ok(theme)
+    }
+
+    pub fn style(&self, group: &str) -> ContentStyle {
+        let mut group = group;
+        loop {
+            if let Some(style) = self.groups.get(group) {
+                return *style;
+            }
+            match fallback(group) {
+                Some(next) => group = next,
+                None => return self.groups.get("Normal").copied().unwrap_or_default(),
+            }
+        }
+    }
+
+    // The style of `group` drawn on top of `Normal`, so that groups which
+    // only set a foreground color keep the background of the theme.
+    pub fn text_style(&self, group: &str) -> ContentStyle {
+        let normal = self.style("Normal");
+        let mut style = self.style(group);
+        if style.foreground_color.is_none() {
+            style.foreground_color = normal.foreground_color;
+        }
+        if style.background_color.is_none() {
+            style.background_color = normal.background_color;
+        }
+        style
+    }
+}
+
+// Loads a theme by name from the `colors` directory of `config_dir`, falling
+// back to the built-in themes. Names cannot reach outside that directory.
+pub fn load(name: &str, config_dir: Option<&Path>) -> Result<Theme, String> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(format!("Invalid color scheme name '{}'", name));
+    }
+    if let Some(path) = config_dir.map(|dir| dir.join("colors").join(format!("{}.toml", name))) {
+        if let Ok(source) = fs::read_to_string(&path) {
+            return Theme::from_toml(name, &source)
+                .map_err(|err| format!("{}: {}", path.display(), err));
+        }
+    }
+    builtin(name).ok_or_else(|| format!("Cannot find color scheme '{}'", name))
+}
+
+// One of the themes that come with the editor.
+pub fn builtin(name: &str) -> Option<Theme> {
+    let (_, source) = BUILTIN_THEMES
+        .iter()
+        .find(|(builtin, _)| *builtin == name)?;
+    Some(Theme::from_toml(name, source).expect("invalid built-in theme"))
+}
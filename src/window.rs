@@ -0,0 +1,448 @@
+use crate::editor::{Editor, Effect};
+use crate::options::Options;
+
+// Which way a window is split: `Horizontal` puts the windows above each
+// other, `Vertical` side by side.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Split {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+
+    fn extent(&self, split: Split) -> usize {
+        match split {
+            Split::Horizontal => self.height,
+            Split::Vertical => self.width,
+        }
+    }
+}
+
+// A view on a buffer. The current window keeps its cursor and scroll
+// position in the buffer itself, the others keep theirs here.
+#[derive(Clone)]
+pub struct Window {
+    pub id: usize,
+    pub buffer: usize,
+    pub row_pos: usize,
+    pub col_pos: usize,
+    pub row_scroll_pos: usize,
+    pub col_scroll_pos: usize,
+    pub options: Options,
+}
+
+// How the windows divide the screen: a tree of splits, each child with its
+// size along the split. Windows side by side are separated by a column.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Layout {
+    Window(usize),
+    Split(Split, Vec<(Layout, usize)>),
+}
+
+// The smallest a window can be: one line of text and its status line, or
+// one column.
+fn min_size(split: Split) -> usize {
+    match split {
+        Split::Horizontal => 2,
+        Split::Vertical => 1,
+    }
+}
+
+impl Layout {
+    // The ids of the windows, from the top left to the bottom right.
+    pub fn windows(&self) -> Vec<usize> {
+        match self {
+            Layout::Window(id) => vec![*id],
+            Layout::Split(_, children) => children.iter().flat_map(|(c, _)| c.windows()).collect(),
+        }
+    }
+
+    fn contains(&self, id: usize) -> bool {
+        match self {
+            Layout::Window(window) => *window == id,
+            Layout::Split(_, children) => children.iter().any(|(c, _)| c.contains(id)),
+        }
+    }
+
+    // The area of each window within `rect`, and of the columns that
+    // separate windows side by side.
+    pub fn rects(&self, rect: Rect) -> (Vec<(usize, Rect)>, Vec<Rect>) {
+        let mut windows = Vec::new();
+        let mut separators = Vec::new();
+        self.place(rect, &mut windows, &mut separators);
+        (windows, separators)
+    }
+
+    fn place(&self, rect: Rect, windows: &mut Vec<(usize, Rect)>, separators: &mut Vec<Rect>) {
+        let (split, children) = match self {
+            Layout::Window(id) => return windows.push((*id, rect)),
+            Layout::Split(split, children) => (*split, children),
+        };
+        let rects = child_rects(split, children, rect);
+        for (i, ((child, _), rect)) in children.iter().zip(&rects).enumerate() {
+            child.place(*rect, windows, separators);
+            if split == Split::Vertical && i + 1 < rects.len() {
+                separators.push(Rect {
+                    x: rect.x + rect.width,
+                    width: 1,
+                    ..*rect
+                });
+            }
+        }
+    }
+
+    // Gives all windows in `rect` the same size, as far as that goes.
+    pub fn equalize(&mut self, rect: Rect) {
+        let Layout::Split(split, children) = self else {
+            return;
+        };
+        let count = children.len();
+        let space = match split {
+            Split::Horizontal => rect.height,
+            Split::Vertical => rect.width.saturating_sub(count - 1),
+        };
+        for (i, (child, size)) in children.iter_mut().enumerate() {
+            *size = space / count + usize::from(i < space % count);
+            let child_rect = match split {
+                Split::Horizontal => Rect {
+                    height: *size,
+                    ..rect
+                },
+                Split::Vertical => Rect {
+                    width: *size,
+                    ..rect
+                },
+            };
+            child.equalize(child_rect);
+        }
+    }
+
+    // Puts window `new` next to window `id`, before it.
+    pub fn split(&mut self, id: usize, new: usize, split: Split) {
+        match self {
+            Layout::Window(window) if *window == id => {
+                let children = vec![(Layout::Window(new), 0), (Layout::Window(id), 0)];
+                *self = Layout::Split(split, children);
+            }
+            Layout::Window(_) => {}
+            Layout::Split(direction, children) => {
+                let index = children.iter().position(|(c, _)| c.contains(id));
+                let Some(index) = index else {
+                    return;
+                };
+                match &children[index].0 {
+                    Layout::Window(_) if *direction == split => {
+                        children.insert(index, (Layout::Window(new), 0));
+                    }
+                    _ => children[index].0.split(id, new, split),
+                }
+            }
+        }
+    }
+
+    // Takes window `id` out, leaving its neighbours the space. A split with
+    // a single window left is replaced by that window.
+    pub fn remove(&mut self, id: usize) {
+        let Layout::Split(_, children) = self else {
+            return;
+        };
+        children.retain(|(c, _)| *c != Layout::Window(id));
+        for (child, _) in children.iter_mut() {
+            child.remove(id);
+        }
+        if children.len() == 1 {
+            *self = children.remove(0).0;
+        }
+    }
+
+    // Makes window `id` `delta` rows or columns larger, taking the space from
+    // or giving it to the window after it, or before it for the last one.
+    // The innermost split in the right direction is changed. Returns false
+    // if there is no such split.
+    pub fn resize(&mut self, id: usize, split: Split, delta: isize, rect: Rect) -> bool {
+        let Layout::Split(direction, children) = self else {
+            return false;
+        };
+        let Some(index) = children.iter().position(|(c, _)| c.contains(id)) else {
+            return false;
+        };
+        let rects = child_rects(*direction, children, rect);
+        if children[index].0.resize(id, split, delta, rects[index]) {
+            return true;
+        }
+        if *direction != split || children.len() < 2 {
+            return false;
+        }
+        let other = if index + 1 < children.len() {
+            index + 1
+        } else {
+            index - 1
+        };
+        let min = min_size(split);
+        let (size, other_size) = (rects[index].extent(split), rects[other].extent(split));
+        let grow = delta.clamp(
+            -(size.saturating_sub(min) as isize),
+            other_size.saturating_sub(min) as isize,
+        );
+        for ((_, size), rect) in children.iter_mut().zip(&rects) {
+            *size = rect.extent(split);
+        }
+        children[index].1 = size.saturating_add_signed(grow);
+        children[other].1 = other_size.saturating_add_signed(-grow);
+        let rects = child_rects(*direction, children, rect);
+        for i in [index, other] {
+            children[i].0.equalize(rects[i]);
+        }
+        true
+    }
+}
+
+// The areas of the children of a split within `rect`.
+fn child_rects(split: Split, children: &[(Layout, usize)], rect: Rect) -> Vec<Rect> {
+    let mut offset = 0;
+    let mut rects = Vec::new();
+    for (i, (_, size)) in children.iter().enumerate() {
+        let left = rect.extent(split).saturating_sub(offset);
+        // The last child takes what is left, whatever the sizes say.
+        let size = match i + 1 == children.len() {
+            true => left,
+            false => (*size).min(left),
+        };
+        rects.push(match split {
+            Split::Horizontal => Rect {
+                y: rect.y + offset,
+                height: size,
+                ..rect
+            },
+            Split::Vertical => Rect {
+                x: rect.x + offset,
+                width: size,
+                ..rect
+            },
+        });
+        offset += size;
+        if split == Split::Vertical {
+            offset += 1;
+        }
+    }
+    rects
+}
+
+// The window that has the cell at `x`, `y`.
+pub fn window_at(windows: &[(usize, Rect)], x: usize, y: usize) -> Option<usize> {
+    windows
+        .iter()
+        .find(|(_, rect)| rect.contains(x, y))
+        .map(|(id, _)| *id)
+}
+
+impl Editor {
+    // The part of the screen that the windows divide, above the command
+    // line.
+    pub fn window_area(&self) -> Rect {
+        let tabline = self.tabline_rows();
+        Rect {
+            x: 0,
+            y: tabline,
+            width: self.screen_cols,
+            height: self.screen_rows.saturating_sub(1 + tabline),
+        }
+    }
+
+    pub fn window_rects(&self) -> (Vec<(usize, Rect)>, Vec<Rect>) {
+        self.layout.rects(self.window_area())
+    }
+
+    // Sizes the text of the current window to its place in the layout, and
+    // keeps the cursor of every window in view. A single window has no
+    // status line of its own.
+    pub(crate) fn fit_windows(&mut self) {
+        let (rects, _) = self.window_rects();
+        let status = usize::from(rects.len() > 1);
+        for (id, rect) in rects {
+            let rows = rect.height.saturating_sub(status).max(1);
+            if id == self.window {
+                self.num_rows = rows;
+                self.num_cols = rect.width.max(1);
+            } else if let Some(window) = self.windows.iter_mut().find(|w| w.id == id) {
+                window.row_scroll_pos = window
+                    .row_scroll_pos
+                    .clamp((window.row_pos + 1).saturating_sub(rows), window.row_pos);
+            }
+        }
+        self.scroll_to_cursor();
+    }
+
+    // The current window as the other windows are kept.
+    pub(crate) fn current_window(&self) -> Window {
+        Window {
+            id: self.window,
+            buffer: self.file.number,
+            row_pos: self.file.row_pos,
+            col_pos: self.file.col_pos,
+            row_scroll_pos: self.file.row_scroll_pos,
+            col_scroll_pos: self.file.col_scroll_pos,
+            options: self.window_options.clone(),
+        }
+    }
+
+    pub fn enter_window(&mut self, id: usize) {
+        let Some(index) = self.windows.iter().position(|w| w.id == id) else {
+            return;
+        };
+        let target = self.windows.remove(index);
+        self.windows.push(self.current_window());
+        self.load_window(target);
+    }
+
+    // Makes a window that was kept with the others the current one.
+    pub(crate) fn load_window(&mut self, target: Window) {
+        self.window = target.id;
+        self.window_options = target.options;
+        if target.buffer != self.file.number {
+            let file = self
+                .take_buffer(target.buffer)
+                .expect("window without buffer");
+            self.put_buffer(file);
+        }
+        // The buffer may have changed in another window since.
+        let row = target.row_pos.min(self.file.lines.len() - 1);
+        self.file.row_pos = row;
+        self.file.col_pos = target.col_pos;
+        self.clamp_cursor();
+        self.file.row_scroll_pos = target.row_scroll_pos.min(row);
+        self.file.col_scroll_pos = target.col_scroll_pos;
+        self.fit_windows();
+    }
+
+    // Splits the current window in two, both showing the current buffer.
+    // The new window goes above or to the left and becomes the current one.
+    pub fn split_window(&mut self, split: Split) -> Result<(), String> {
+        let id = self.last_window + 1;
+        let mut layout = self.layout.clone();
+        layout.split(self.window, id, split);
+        layout.equalize(self.window_area());
+        let (rects, _) = layout.rects(self.window_area());
+        if rects
+            .iter()
+            .any(|(_, rect)| rect.height < 2 || rect.width < 1)
+        {
+            return Err("Not enough room".to_string());
+        }
+        self.last_window = id;
+        self.layout = layout;
+        self.windows.push(self.current_window());
+        self.window = id;
+        self.fit_windows();
+        Ok(())
+    }
+
+    // Whether the current window is the only one in all tab pages.
+    pub(crate) fn only_window_left(&self) -> bool {
+        self.windows.is_empty() && self.tabs.is_empty()
+    }
+
+    // Closes a window, keeping its buffer in the list. Closing the current
+    // window makes the one before it current, or else the one after it.
+    // Closing the last window of a tab page closes the tab page.
+    pub fn close_window(&mut self, id: usize) -> Result<(), String> {
+        let order = self.layout.windows();
+        if order.len() == 1 {
+            return match self.tabs.is_empty() {
+                true => Err("Cannot close last window".to_string()),
+                false => self.close_tab(),
+            };
+        }
+        if id == self.window {
+            let index = order.iter().position(|&w| w == id).unwrap();
+            self.enter_window(order[index.checked_sub(1).unwrap_or(1)]);
+        }
+        self.windows.retain(|window| window.id != id);
+        self.layout.remove(id);
+        self.layout.equalize(self.window_area());
+        self.fit_windows();
+        Ok(())
+    }
+
+    // Closes all windows but the current one.
+    pub fn only_window(&mut self) {
+        self.windows.clear();
+        self.layout = Layout::Window(self.window);
+        self.fit_windows();
+    }
+
+    // `:q` closes the current window, and quits once it is the last one.
+    pub(crate) fn quit_window(&mut self, bang: bool) -> Result<Effect, String> {
+        match self.only_window_left() {
+            true => self.quit_command(bang),
+            false => {
+                self.close_window(self.window)?;
+                Ok(Effect::Redraw)
+            }
+        }
+    }
+
+    // Goes to the window next to the current one in direction `h`, `j`, `k`
+    // or `l`, at the height or column of the cursor.
+    fn move_to_window(&mut self, direction: char) {
+        let (rects, _) = self.window_rects();
+        let Some(&(_, rect)) = rects.iter().find(|(id, _)| *id == self.window) else {
+            return;
+        };
+        let (x, y) = self.cursor_position();
+        let (x, y) = (rect.x + x.min(rect.width - 1), rect.y + y);
+        // Windows side by side have a separator column between them.
+        let (x, y) = match direction {
+            'h' => (rect.x.checked_sub(2), Some(y)),
+            'l' => (Some(rect.x + rect.width + 1), Some(y)),
+            'k' => (Some(x), rect.y.checked_sub(1)),
+            _ => (Some(x), Some(rect.y + rect.height)),
+        };
+        if let Some(id) = x.zip(y).and_then(|(x, y)| window_at(&rects, x, y)) {
+            self.enter_window(id);
+        }
+    }
+
+    pub(crate) fn window_command(&mut self, command: char) -> Result<Effect, String> {
+        let order = self.layout.windows();
+        let index = order.iter().position(|&id| id == self.window).unwrap();
+        let area = self.window_area();
+        match command {
+            's' => self.split_window(Split::Horizontal)?,
+            'v' => self.split_window(Split::Vertical)?,
+            'h' | 'j' | 'k' | 'l' => self.move_to_window(command),
+            'w' => self.enter_window(order[(index + 1) % order.len()]),
+            'W' => self.enter_window(order[(index + order.len() - 1) % order.len()]),
+            'c' => self.close_window(self.window)?,
+            'q' => return self.quit_window(false),
+            'o' => self.only_window(),
+            '=' => {
+                self.layout.equalize(area);
+                self.fit_windows();
+            }
+            '+' | '-' | '<' | '>' => {
+                let split = match command {
+                    '+' | '-' => Split::Horizontal,
+                    _ => Split::Vertical,
+                };
+                let delta = if matches!(command, '+' | '>') { 1 } else { -1 };
+                self.layout.resize(self.window, split, delta, area);
+                self.fit_windows();
+            }
+            _ => return Ok(Effect::None),
+        }
+        Ok(Effect::Redraw)
+    }
+}
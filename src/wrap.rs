@@ -0,0 +1,289 @@
+use crate::buffer::EditorFile;
+use crate::editor::Editor;
+use crate::options::{OptionValue, Options};
+use crate::screen;
+use std::ops::Range;
+
+// How the lines of a window are wrapped with `wrap` set.
+pub struct Wrap {
+    // The columns of text in the window.
+    pub width: usize,
+    pub tabstop: usize,
+    pub linebreak: bool,
+    pub showbreak: String,
+    pub breakindent: bool,
+}
+
+// The part of a line that is shown on one row of the screen.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Segment {
+    pub bytes: Range<usize>,
+    // The display columns of the part in the whole line.
+    pub cols: Range<usize>,
+    // The width of the indent and `showbreak` before a part that continues
+    // the line.
+    pub indent: usize,
+}
+
+impl Wrap {
+    // Splits a line into the parts that fit the width of the window. With
+    // `linebreak`, lines are broken after a blank where there is one.
+    pub fn segments(&self, line: &str) -> Vec<Segment> {
+        let chars: Vec<_> = line.char_indices().collect();
+        let byte = |i: usize| chars.get(i).map_or(line.len(), |&(b, _)| b);
+        let mut next_indent = screen::display_width(&self.showbreak, self.tabstop);
+        if self.breakindent {
+            let blanks = line.len() - line.trim_start_matches([' ', '\t']).len();
+            next_indent += screen::display_width(&line[..blanks], self.tabstop);
+        }
+        if next_indent >= self.width {
+            next_indent = 0;
+        }
+        let mut segments = Vec::new();
+        let (mut start, mut start_col, mut indent) = (0, 0, 0);
+        let mut blank = None;
+        let (mut i, mut col) = (0, 0);
+        while i < chars.len() {
+            let c = chars[i].1;
+            let width = screen::char_width(c, col, self.tabstop);
+            if i > start && col + width - start_col > self.width - indent {
+                let (end, end_col) = match blank {
+                    Some(blank) if self.linebreak => blank,
+                    _ => (i, col),
+                };
+                segments.push(Segment {
+                    bytes: byte(start)..byte(end),
+                    cols: start_col..end_col,
+                    indent,
+                });
+                (start, start_col, indent) = (end, end_col, next_indent);
+                (i, col) = (end, end_col);
+                blank = None;
+                continue;
+            }
+            i += 1;
+            col += width;
+            if matches!(c, ' ' | '\t') {
+                blank = Some((i, col));
+            }
+        }
+        segments.push(Segment {
+            bytes: byte(start)..line.len(),
+            cols: start_col..col,
+            indent,
+        });
+        segments
+    }
+
+    // How many rows of the screen a line takes.
+    pub fn height(&self, line: &str) -> usize {
+        self.segments(line).len()
+    }
+}
+
+// The byte of the character at display column `target` in a segment, or
+// the last one if the segment is shorter. Only the last segment of a line
+// lets the cursor go past its end.
+fn byte_at(line: &str, segment: &Segment, target: usize, last: bool, tabstop: usize) -> usize {
+    let mut col = segment.cols.start;
+    let mut previous = segment.bytes.start;
+    for (i, c) in line[segment.bytes.clone()].char_indices() {
+        let width = screen::char_width(c, col, tabstop);
+        if col + width > target {
+            return segment.bytes.start + i;
+        }
+        col += width;
+        previous = segment.bytes.start + i;
+    }
+    match last {
+        true => segment.bytes.end,
+        false => previous,
+    }
+}
+
+impl Editor {
+    // How the lines of a window with `options` on `file` wrap in `width`
+    // columns, or None if they don't.
+    pub(crate) fn wrap(&self, options: &Options, file: &EditorFile, width: usize) -> Option<Wrap> {
+        let flag = |name| matches!(self.window_option(options, name), OptionValue::Bool(true));
+        if !flag("wrap") {
+            return None;
+        }
+        Some(Wrap {
+            width: width.max(1),
+            tabstop: self.tabstop(file),
+            linebreak: flag("linebreak"),
+            showbreak: self.string("showbreak").to_string(),
+            breakindent: flag("breakindent"),
+        })
+    }
+
+    // The local `tabstop` of `file`, which the lines of a window on it are
+    // laid out with.
+    fn tabstop(&self, file: &EditorFile) -> usize {
+        match self.buffer_option(file, "tabstop") {
+            OptionValue::Number(n) => *n as usize,
+            _ => 8,
+        }
+    }
+
+    fn current_wrap(&self) -> Option<Wrap> {
+        self.wrap(&self.window_options, &self.file, self.text_cols())
+    }
+
+    // The row of the screen that the cursor is on within its line, and the
+    // part of the line shown there. Without `wrap`, that is the part of the
+    // line that fits in the window.
+    fn cursor_segment(&self) -> (usize, Segment, bool) {
+        let line = &self.file.lines[self.file.row_pos].chars;
+        let Some(wrap) = self.current_wrap() else {
+            let tabstop = self.tabstop(&self.file);
+            let left = self.file.col_scroll_pos;
+            let cols = left..left + self.text_cols();
+            let whole = Segment {
+                bytes: 0..line.len(),
+                cols: 0..usize::MAX,
+                indent: 0,
+            };
+            let start = byte_at(line, &whole, cols.start, true, tabstop);
+            let end = byte_at(line, &whole, cols.end, true, tabstop);
+            let last = end == line.len();
+            let segment = Segment {
+                bytes: start..end,
+                cols,
+                indent: 0,
+            };
+            return (0, segment, last);
+        };
+        let mut segments = wrap.segments(line);
+        let index = segments
+            .iter()
+            .rposition(|segment| segment.bytes.start <= self.file.col_pos)
+            .unwrap_or(0);
+        let last = index + 1 == segments.len();
+        (index, segments.swap_remove(index), last)
+    }
+
+    // Where the cursor is in the current window, counting from its top left
+    // corner.
+    pub(crate) fn cursor_position(&self) -> (usize, usize) {
+        let file = &self.file;
+        let gutter = self.gutter_width();
+        let Some(wrap) = self.current_wrap() else {
+            return (
+                gutter + self.cursor_col() - file.col_scroll_pos,
+                file.row_pos - file.row_scroll_pos,
+            );
+        };
+        let rows: usize = (file.row_scroll_pos..file.row_pos)
+            .map(|row| wrap.height(&file.lines[row].chars))
+            .sum();
+        let (index, segment, _) = self.cursor_segment();
+        let x = segment.indent + self.cursor_col() - segment.cols.start;
+        let y = (rows + index).saturating_sub(file.col_scroll_pos);
+        (gutter + x.min(wrap.width - 1), y)
+    }
+
+    // Like `scroll_to_cursor` for a window whose lines wrap: the lines above
+    // the cursor scroll out of view until all rows of its line fit. A line
+    // taller than the window scrolls by rows of its own, which are counted
+    // by `col_scroll_pos` as vim's `skipcol` does.
+    pub(crate) fn scroll_wrapped(&mut self) -> Option<bool> {
+        let wrap = self.current_wrap()?;
+        let (index, _, _) = self.cursor_segment();
+        let file = &self.file;
+        let (top, left) = (file.row_scroll_pos, file.col_scroll_pos);
+        let row = file.row_pos;
+        let last = file.lines.len().saturating_sub(1);
+        let so = self.scrolloff();
+        let height = |row: usize| wrap.height(&file.lines[row].chars);
+        let mut new_top = top.min(row.saturating_sub(so));
+        let from_cursor: usize = (row..=(row + so).min(last)).map(height).sum();
+        let mut rows = (new_top..row).map(height).sum::<usize>() + from_cursor;
+        while rows > self.num_rows && new_top < row {
+            rows -= height(new_top);
+            new_top += 1;
+        }
+        let rows = height(row);
+        let skip = match new_top == row && rows > self.num_rows {
+            true => {
+                let skip = if top == row { left } else { 0 };
+                let skip = skip
+                    .min(index)
+                    .max((index + 1).saturating_sub(self.num_rows));
+                skip.min(rows - self.num_rows)
+            }
+            false => 0,
+        };
+        self.file.row_scroll_pos = new_top;
+        self.file.col_scroll_pos = skip;
+        Some((new_top, skip) != (top, left))
+    }
+
+    // How many lines fit in the current window from the top one on.
+    pub(crate) fn visible_lines(&self) -> usize {
+        let Some(wrap) = self.current_wrap() else {
+            return self.num_rows;
+        };
+        let mut rows = 0;
+        let lines = &self.file.lines[self.file.row_scroll_pos..];
+        let count = lines
+            .iter()
+            .take_while(|line| {
+                rows += wrap.height(&line.chars);
+                rows <= self.num_rows + self.file.col_scroll_pos
+            })
+            .count();
+        count.max(1)
+    }
+
+    // `gj` and `gk`, which move to the next or previous row of the screen
+    // rather than line when lines wrap.
+    pub fn move_display_line(&mut self, down: bool) {
+        let Some(wrap) = self.current_wrap() else {
+            let row = self.file.row_pos;
+            self.file.row_pos = match down {
+                true => (row + 1).min(self.file.lines.len() - 1),
+                false => row.saturating_sub(1),
+            };
+            return self.clamp_cursor();
+        };
+        let (index, segment, _) = self.cursor_segment();
+        let target = self.cursor_col() - segment.cols.start;
+        let row = self.file.row_pos;
+        let (row, index) = match down {
+            true if index + 1 < wrap.height(&self.file.lines[row].chars) => (row, index + 1),
+            true if row + 1 < self.file.lines.len() => (row + 1, 0),
+            false if index > 0 => (row, index - 1),
+            false if row > 0 => (row - 1, wrap.height(&self.file.lines[row - 1].chars) - 1),
+            _ => return,
+        };
+        let line = &self.file.lines[row].chars;
+        let segments = wrap.segments(line);
+        let segment = &segments[index];
+        let last = index + 1 == segments.len();
+        self.file.row_pos = row;
+        self.file.col_pos = byte_at(
+            line,
+            segment,
+            segment.cols.start + target,
+            last,
+            wrap.tabstop,
+        );
+    }
+
+    // `g0`, the first character on the row of the screen.
+    pub fn move_to_display_start(&mut self) {
+        self.file.col_pos = self.cursor_segment().1.bytes.start;
+    }
+
+    // `g$`, the end of the row of the screen.
+    pub fn move_to_display_end(&mut self) {
+        let (_, segment, last) = self.cursor_segment();
+        let line = &self.file.lines[self.file.row_pos].chars;
+        self.file.col_pos = match last {
+            true => line.len(),
+            false => byte_at(line, &segment, usize::MAX, false, self.tabstop(&self.file)),
+        };
+    }
+}
@@ -0,0 +1,1661 @@
+use crossterm::event::{Event, KeyEvent};
+use crossterm::style::Color;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io, process};
+use viprust::cli::{self, Args, Command, Split};
+use viprust::config;
+use viprust::highlight::{self, TokenClass};
+use viprust::key;
+use viprust::terminal::{Backend, MemoryBackend};
+use viprust::theme::{self, Theme};
+use viprust::{Editor, EditorAction, EditorMode, Effect, Sign, WriteRequest};
+
+// Runs the editor on a terminal in memory and types keys into it.
+struct Harness {
+    editor: Editor,
+    backend: MemoryBackend,
+    quit: bool,
+}
+
+impl Harness {
+    fn new(text: &str) -> Harness {
+        Harness::with_size(text, 40, 6)
+    }
+
+    fn with_size(text: &str, cols: usize, rows: usize) -> Harness {
+        let mut editor = Editor::builtin((cols, rows));
+        editor.load_text("test.txt", text);
+        Harness {
+            editor,
+            backend: MemoryBackend::new(cols, rows),
+            quit: false,
+        }
+    }
+
+    // A harness on a Rust file, which `=` reindents.
+    fn rust(text: &str) -> Harness {
+        let mut h = Harness::new("");
+        h.editor.load_text("test.rs", text);
+        h
+    }
+
+    // The highlighted parts of line `row`, with their classes.
+    fn tokens(&mut self, row: usize) -> Vec<(String, TokenClass)> {
+        let file = &mut self.editor.file;
+        let highlighter = file.highlighter.as_mut().unwrap();
+        let spans = highlighter.highlight(&file.lines, row..row + 1).remove(0);
+        let line = &file.lines[row].chars;
+        spans
+            .iter()
+            .map(|span| (line[span.start..span.end].to_string(), span.class))
+            .collect()
+    }
+
+    // Types `keys`, written in vim notation, and runs the editor until it has
+    // handled all of them or quit.
+    fn keys(&mut self, keys: &str) -> &mut Harness {
+        for key in key::parse_keys(keys).unwrap() {
+            let event = Event::Key(KeyEvent::from(key));
+            self.backend.events.push_back(event);
+        }
+        match self.editor.run(&mut self.backend) {
+            Ok(()) => self.quit = true,
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{}", err),
+        }
+        self
+    }
+
+    fn buffer(&self) -> String {
+        let lines: Vec<_> = self
+            .editor
+            .file
+            .lines
+            .iter()
+            .map(|line| line.chars.as_str())
+            .collect();
+        lines.join("\n")
+    }
+
+    fn cursor(&self) -> (usize, usize) {
+        (self.editor.file.row_pos, self.editor.file.col_pos)
+    }
+
+    fn mode(&self) -> &EditorMode {
+        &self.editor.mode
+    }
+
+    // The last frame drawn, one line per row.
+    fn screen(&self) -> String {
+        let grid = &self.backend.frame.as_ref().unwrap().grid;
+        let rows: Vec<_> = (0..grid.height).map(|y| grid.row_text(y)).collect();
+        rows.join("\n")
+    }
+
+    fn screen_cursor(&self) -> (usize, usize) {
+        self.backend.frame.as_ref().unwrap().cursor
+    }
+}
+
+// A path for a test to write to, which does not exist yet.
+fn temp_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("viprust-{}-{}", process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn insert_text() {
+    let mut h = Harness::new("");
+    h.keys("ifoo");
+    assert_eq!(h.mode(), &EditorMode::Insert);
+    h.keys("<Esc>");
+    assert_eq!(h.buffer(), "foo");
+    assert_eq!(h.cursor(), (0, 3));
+    assert_eq!(h.mode(), &EditorMode::Normal);
+}
+
+#[test]
+fn move_cursor() {
+    let mut h = Harness::new("abc\ndefg\nh");
+    h.keys("jll");
+    assert_eq!(h.cursor(), (1, 2));
+    h.keys("$");
+    assert_eq!(h.cursor(), (1, 4));
+    h.keys("j");
+    assert_eq!(h.cursor(), (2, 1));
+    h.keys("k0<Up>");
+    assert_eq!(h.cursor(), (0, 0));
+}
+
+#[test]
+fn backspace_joins_lines() {
+    let mut h = Harness::new("abc\ndef");
+    h.keys("ji<BS><Esc>");
+    assert_eq!(h.buffer(), "abcdef");
+    assert_eq!(h.cursor(), (0, 3));
+}
+
+#[test]
+fn split_line_keeps_indent() {
+    let mut h = Harness::new("    foo bar");
+    h.keys(":set ai<CR>$hhhhi<CR>x<Esc>");
+    assert_eq!(h.buffer(), "    foo\n    xbar");
+    assert_eq!(h.cursor(), (1, 5));
+}
+
+#[test]
+fn split_line_without_autoindent() {
+    let mut h = Harness::new("    foo");
+    h.keys(":set noai nosi<CR>0lllli<CR><Esc>");
+    assert_eq!(h.buffer(), "    \nfoo");
+    let mut h = Harness::new("    foo");
+    h.keys(":set ai<CR>0lllli<CR><Esc>");
+    assert_eq!(h.buffer(), "\n    foo");
+}
+
+#[test]
+fn split_line_between_brackets() {
+    let mut h = Harness::rust("fn main() {}");
+    h.keys("$hi<CR>x<Esc>");
+    assert_eq!(h.buffer(), "fn main() {\n    x\n}");
+}
+
+#[test]
+fn indent_options_follow_filetype() {
+    let mut h = Harness::new("fn main() {}");
+    assert!(!h.editor.flag("autoindent") && !h.editor.flag("expandtab"));
+    assert_eq!(h.editor.number("shiftwidth"), 8);
+    h.keys("$hi<CR><Esc>");
+    assert_eq!(h.buffer(), "fn main() {\n}");
+    h.keys("gg=G");
+    assert_eq!(h.buffer(), "fn main() {\n}");
+
+    let mut h = Harness::new("fn main() {\nx\n}");
+    h.keys(":set ft=rust<CR>");
+    assert!(h.editor.flag("smartindent"));
+    assert_eq!(h.editor.number("shiftwidth"), 4);
+    h.keys("gg=G");
+    assert_eq!(h.buffer(), "fn main() {\n    x\n}");
+}
+
+#[test]
+fn reindent_nested_brackets() {
+    let mut h =
+        Harness::rust("fn main() {\nlet v = vec![\n(1, 2),\n];\nif x {\nfoo(a, [\nb,\n]);\n}\n}");
+    h.keys("=G");
+    let text = [
+        "fn main() {",
+        "    let v = vec![",
+        "        (1, 2),",
+        "    ];",
+        "    if x {",
+        "        foo(a, [",
+        "            b,",
+        "        ]);",
+        "    }",
+        "}",
+    ];
+    assert_eq!(h.buffer(), text.join("\n"));
+    assert_eq!(h.cursor(), (0, 0));
+}
+
+#[test]
+fn reindent_continuation_lines() {
+    let mut h = Harness::rust(
+        "fn f() {\nlet x = a +\nb;\nlet y = z\n.map(f)\n.sum();\nmatch x {\nA =>\n1,\n}\n}",
+    );
+    h.keys("=G");
+    let text = [
+        "fn f() {",
+        "    let x = a +",
+        "        b;",
+        "    let y = z",
+        "        .map(f)",
+        "        .sum();",
+        "    match x {",
+        "        A =>",
+        "            1,",
+        "    }",
+        "}",
+    ];
+    assert_eq!(h.buffer(), text.join("\n"));
+}
+
+#[test]
+fn reindent_comments_and_literals() {
+    let text = [
+        "fn f() {",
+        "/* a {",
+        "* b (",
+        "*/",
+        "let s = \"{ // (\";",
+        "let r = r#\"a \"{\" b",
+        "  [ raw",
+        "\"#;",
+        "let c = '\\'';",
+        "let d = '{';",
+        "g::<'a>(x); // {",
+        "}",
+    ];
+    let mut h = Harness::rust(&text.join("\n"));
+    h.keys("=G");
+    let text = [
+        "fn f() {",
+        "    /* a {",
+        "     * b (",
+        "     */",
+        "    let s = \"{ // (\";",
+        "    let r = r#\"a \"{\" b",
+        "  [ raw",
+        "\"#;",
+        "    let c = '\\'';",
+        "    let d = '{';",
+        "    g::<'a>(x); // {",
+        "}",
+    ];
+    assert_eq!(h.buffer(), text.join("\n"));
+}
+
+#[test]
+fn reindent_range() {
+    let text = "fn f() {\nx;\ny;\nif z {\nw;\n}\n}";
+    let mut h = Harness::rust(text);
+    h.keys("j==");
+    assert_eq!(h.buffer(), "fn f() {\n    x;\ny;\nif z {\nw;\n}\n}");
+    h.keys("j=j");
+    assert_eq!(h.buffer(), "fn f() {\n    x;\n    y;\n    if z {\nw;\n}\n}");
+    h.keys("jj=G");
+    let text = "fn f() {\n    x;\n    y;\n    if z {\n        w;\n    }\n}";
+    assert_eq!(h.buffer(), text);
+    assert_eq!(h.cursor(), (4, 8));
+    h.keys("=k");
+    assert_eq!(h.buffer(), text);
+}
+
+#[test]
+fn insert_mapping() {
+    let mut h = Harness::new("");
+    h.keys(":inoremap jk <lt>Esc><CR>");
+    h.keys("iajb");
+    assert_eq!(h.buffer(), "ajb");
+    h.keys("jk");
+    assert_eq!(h.buffer(), "ajb");
+    assert_eq!(h.mode(), &EditorMode::Normal);
+}
+
+#[test]
+fn mapping_timeout() {
+    let mut h = Harness::new("");
+    h.keys(":imap j X<CR>:imap jk <lt>Esc><CR>");
+    // The harness runs out of keys after `j`, which is when `timeoutlen`
+    // would expire.
+    h.keys("ij");
+    assert_eq!(h.buffer(), "X");
+    h.keys("k");
+    assert_eq!(h.buffer(), "Xk");
+    h.keys("jk");
+    assert_eq!(h.mode(), &EditorMode::Normal);
+    assert_eq!(h.buffer(), "Xk");
+}
+
+#[test]
+fn mapping_leader() {
+    let mut h = Harness::new("abc def");
+    h.keys(":nmap <leader>e $<CR>\\e");
+    assert_eq!(h.cursor(), (0, 7));
+    h.keys(":set mapleader=,<CR>:nmap <Leader>b 0<CR>,b");
+    assert_eq!(h.cursor(), (0, 0));
+    h.keys("\\e");
+    assert_eq!(h.cursor(), (0, 7));
+}
+
+#[test]
+fn unmap_and_mapclear() {
+    let mut h = Harness::new("abc def");
+    h.keys(":nmap Q $<CR>:nmap W 0<CR>Q");
+    assert_eq!(h.cursor(), (0, 7));
+    h.keys(":unmap Q<CR>0Q");
+    assert_eq!(h.cursor(), (0, 0));
+    h.keys(":unmap Q<CR>");
+    assert_eq!(h.editor.message.as_deref(), Some("No such mapping: Q"));
+    h.keys("$W");
+    assert_eq!(h.cursor(), (0, 0));
+    h.keys(":mapclear<CR>$W");
+    assert_eq!(h.cursor(), (0, 7));
+}
+
+#[test]
+fn recursive_mappings() {
+    let mut h = Harness::new("abc def");
+    h.keys(":nmap X $<CR>:nmap Y X<CR>:nnoremap Z X<CR>Y");
+    assert_eq!(h.cursor(), (0, 7));
+    h.keys("0Z");
+    assert_eq!(h.cursor(), (0, 0));
+    h.keys(":nmap P R<CR>:nmap R P<CR>P");
+    assert_eq!(h.editor.message.as_deref(), Some("Recursive mapping"));
+    h.keys("$");
+    assert_eq!(h.cursor(), (0, 7));
+}
+
+#[test]
+fn mappings_from_config() {
+    let source = "[keys.normal]\nH = \"0\"\nL = \"$\"\n\"<leader>l\" = { keys = \"L\", remap = true }\n\"<leader>n\" = \"L\"\n";
+    let config = config::parse(Path::new("config.toml"), source)
+        .ok()
+        .unwrap();
+    let theme = theme::builtin("default").unwrap();
+    let mut h = Harness::new("");
+    h.editor = Editor::new(Vec::new(), theme, config, None, (40, 6));
+    assert!(h.editor.apply_startup_config().is_empty());
+    h.editor.load_text("test.txt", "abc def");
+    h.keys("L");
+    assert_eq!(h.cursor(), (0, 7));
+    h.keys("H");
+    assert_eq!(h.cursor(), (0, 0));
+    h.keys("\\n");
+    assert_eq!(h.cursor(), (0, 0));
+    h.keys("\\l");
+    assert_eq!(h.cursor(), (0, 7));
+}
+
+#[test]
+fn apply_actions() {
+    let mut h = Harness::new("abc");
+    let effect = h.editor.apply(EditorAction::MoveRight);
+    assert_eq!(effect, Ok(Effect::Redraw));
+    assert_eq!(h.cursor(), (0, 1));
+    assert_eq!(h.editor.execute("q"), Ok(Effect::Quit));
+    let write = Effect::Write(WriteRequest {
+        path: PathBuf::from("test.txt"),
+        lines: 0..1,
+        append: false,
+        quit: true,
+        force: false,
+        rename: false,
+    });
+    assert_eq!(h.editor.execute("wq"), Ok(write));
+    let message = Effect::Message("nosmartindent".to_string());
+    assert_eq!(h.editor.execute("set nosi si?"), Ok(message));
+    let err = "Not an editor command: foo".to_string();
+    assert_eq!(h.editor.execute("foo"), Err(err));
+}
+
+// Runs `:set` with `args` and returns what it showed.
+fn set(h: &mut Harness, args: &str) -> String {
+    h.editor.message = None;
+    h.keys(&format!(":set {}<CR>", args));
+    h.editor.message.clone().unwrap_or_default()
+}
+
+#[test]
+fn set_number_arithmetic() {
+    let mut h = Harness::new("abc");
+    assert_eq!(set(&mut h, "ts+=2 ts?"), "tabstop=10");
+    assert_eq!(set(&mut h, "ts-=3 ts?"), "tabstop=7");
+    assert_eq!(set(&mut h, "ts^=2 ts?"), "tabstop=14");
+    assert_eq!(
+        set(&mut h, "ts-=20"),
+        "Argument must be positive: tabstop=-6"
+    );
+    assert_eq!(set(&mut h, "ts& ts?"), "tabstop=8");
+    assert_eq!(set(&mut h, "ts=9223372036854775807"), "");
+    assert_eq!(set(&mut h, "ts+=1"), "Invalid argument: tabstop");
+    assert_eq!(set(&mut h, "ts-=-1"), "Invalid argument: tabstop");
+    assert_eq!(set(&mut h, "ts^=2"), "Invalid argument: tabstop");
+    assert_eq!(set(&mut h, "ts?"), "tabstop=9223372036854775807");
+}
+
+#[test]
+fn set_list_arithmetic() {
+    let mut h = Harness::new("abc");
+    assert_eq!(set(&mut h, "gcr?"), "guicursor=n-c:block,i:ver25");
+    set(&mut h, "gcr+=v:hor20");
+    assert_eq!(set(&mut h, "gcr?"), "guicursor=n-c:block,i:ver25,v:hor20");
+    set(&mut h, "gcr+=v:hor20 gcr-=i:ver25");
+    assert_eq!(set(&mut h, "gcr?"), "guicursor=n-c:block,v:hor20");
+    set(&mut h, "gcr^=r:hor50");
+    assert_eq!(set(&mut h, "gcr?"), "guicursor=r:hor50,n-c:block,v:hor20");
+    set(&mut h, "gcr&");
+    assert_eq!(set(&mut h, "gcr?"), "guicursor=n-c:block,i:ver25");
+}
+
+#[test]
+fn set_toggle_and_reset() {
+    let mut h = Harness::new("abc");
+    assert_eq!(set(&mut h, "invnu nu?"), "number");
+    assert_eq!(set(&mut h, "nu! nu?"), "nonumber");
+    assert_eq!(set(&mut h, "invnu nu& nu?"), "nonumber");
+    assert_eq!(set(&mut h, "ts!"), "Invalid argument: ts!");
+    assert_eq!(set(&mut h, "invts"), "Invalid argument: invts");
+    assert_eq!(set(&mut h, "nots"), "Invalid argument: nots");
+}
+
+#[test]
+fn setlocal_in_windows() {
+    let mut h = Harness::with_size("abc", 20, 8);
+    h.keys(":sp<CR>:setlocal nu<CR>");
+    assert_eq!(h.screen().lines().next(), Some("  1 abc"));
+    assert_eq!(h.screen().lines().nth(4), Some("abc"));
+    h.keys("<C-w>j");
+    assert_eq!(set(&mut h, "nu?"), "nonumber");
+    h.keys("<C-w>k");
+    assert_eq!(set(&mut h, "nu?"), "number");
+    set(&mut h, "nonu");
+    h.keys("<C-w>j:setlocal sw=2<CR>");
+    assert_eq!(set(&mut h, "sw?"), "shiftwidth=2");
+    h.keys(":e other.txt<CR>");
+    assert_eq!(set(&mut h, "sw?"), "shiftwidth=8");
+    h.keys("<C-w>k");
+    assert_eq!(h.buffer(), "abc");
+    assert_eq!(set(&mut h, "sw?"), "shiftwidth=2");
+}
+
+#[test]
+fn quit() {
+    let mut h = Harness::new("abc");
+    h.keys(":q<CR>");
+    assert!(h.quit);
+}
+
+#[test]
+fn screen_shows_file() {
+    let mut h = Harness::new("abc\n\tdef");
+    h.keys("j$");
+    assert_eq!(h.screen(), "abc\n        def\n~\n~\n~\ntest.txt");
+    assert_eq!(h.screen_cursor(), (11, 1));
+    h.keys(":se ts=4<CR>");
+    assert_eq!(h.screen(), "abc\n    def\n~\n~\n~\ntest.txt");
+    assert_eq!(h.screen_cursor(), (7, 1));
+}
+
+#[test]
+fn screen_shows_command_line() {
+    let mut h = Harness::new("abc");
+    h.keys(":foo");
+    assert_eq!(h.mode(), &EditorMode::Command);
+    assert_eq!(h.screen().lines().last(), Some(":foo"));
+    assert_eq!(h.screen_cursor(), (4, 5));
+    h.keys("<CR>");
+    assert_eq!(
+        h.screen().lines().last(),
+        Some("Not an editor command: foo")
+    );
+}
+
+#[test]
+fn key_notation_round_trip() {
+    let table = [
+        ("<S-Tab>", "<S-Tab>"),
+        ("<M-x>", "<M-x>"),
+        ("<A-x>", "<M-x>"),
+        ("<F5>", "<F5>"),
+        ("<f12>", "<F12>"),
+        ("<F13>", "<lt>F13>"),
+        ("<c-D>", "<C-d>"),
+        ("<C-d>", "<C-d>"),
+        ("<S-a>", "A"),
+        ("<lt>", "<lt>"),
+        ("<", "<lt>"),
+        ("<Esc", "<lt>Esc"),
+        ("<>", "<lt>>"),
+        ("<bar><Space>", "|<Space>"),
+        ("<esc>:w<cr>", "<Esc>:w<CR>"),
+        ("<C-w>j", "<C-w>j"),
+    ];
+    for (notation, printed) in table {
+        let keys = key::parse_keys(notation).unwrap();
+        assert_eq!(key::to_notation(&keys), printed, "{}", notation);
+        assert_eq!(key::parse_keys(printed).unwrap(), keys, "{}", notation);
+    }
+    assert_eq!(key::parse_keys("<c-D>"), key::parse_keys("<C-d>"));
+    assert!(key::parse_keys("").is_err());
+}
+
+#[test]
+fn screen_shows_pending_keys() {
+    let mut h = Harness::new("abc");
+    h.keys("z");
+    assert_eq!(
+        h.screen().lines().last(),
+        Some("test.txt                      z")
+    );
+}
+
+#[test]
+fn scroll_page() {
+    let text: Vec<String> = (1..=20).map(|n| n.to_string()).collect();
+    let mut h = Harness::new(&text.join("\n"));
+    h.keys("<C-f>");
+    assert_eq!(h.screen(), "4\n5\n6\n7\n8\ntest.txt");
+    assert_eq!(h.cursor(), (3, 0));
+    h.keys("<C-e>");
+    assert_eq!(h.screen(), "5\n6\n7\n8\n9\ntest.txt");
+    assert_eq!(h.cursor(), (4, 0));
+    h.keys("jjzt");
+    assert_eq!(h.screen(), "7\n8\n9\n10\n11\ntest.txt");
+}
+
+#[test]
+fn scroll_horizontally() {
+    let mut h = Harness::with_size(&"x".repeat(50), 20, 3);
+    h.keys("$");
+    assert_eq!(h.screen_cursor(), (19, 0));
+    h.keys("0");
+    assert_eq!(h.screen_cursor(), (0, 0));
+}
+
+#[test]
+fn write_and_quit() {
+    let path = temp_path("write");
+    let mut h = Harness::new("abc\ndef");
+    h.editor.file.name = path.display().to_string();
+    h.keys("ix<Esc>:q<CR>");
+    assert!(!h.quit);
+    let message = "No write since last change (add ! to override)";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+    h.keys(":w<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\ndef\n");
+    let message = format!("\"{}\" 2L, 9B written", path.display());
+    assert_eq!(h.editor.message, Some(message));
+    h.keys(":q<CR>");
+    assert!(h.quit);
+}
+
+#[test]
+fn force_quit() {
+    let mut h = Harness::new("abc");
+    h.keys("ix<Esc>:q!<CR>");
+    assert!(h.quit);
+}
+
+#[test]
+fn write_range() {
+    let path = temp_path("range");
+    let mut h = Harness::new("abc\ndef\nghi");
+    h.keys(&format!(":2,$w {}<CR>", path.display()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "def\nghi\n");
+    h.keys(&format!(":1w {}<CR>", path.display()));
+    let message = "File exists (add ! to override)";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+    h.keys(&format!(":.w! {}<CR>", path.display()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abc\n");
+    h.keys(&format!(":%w >> {}<CR>", path.display()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abc\nabc\ndef\nghi\n");
+    h.keys(":4w<CR>");
+    assert_eq!(h.editor.message.as_deref(), Some("Invalid range"));
+}
+
+#[test]
+fn write_same_file_by_another_name() {
+    let path = temp_path("samefile");
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys(":w<CR>ix<Esc>");
+    let other = path
+        .parent()
+        .unwrap()
+        .join(".")
+        .join(path.file_name().unwrap());
+    h.keys(&format!(":w {}<CR>", other.display()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\n");
+    assert!(!h.editor.modified());
+}
+
+#[test]
+fn write_partial_buffer() {
+    let path = temp_path("partial");
+    let mut h = Harness::new("abc\ndef");
+    h.editor.file.name = path.display().to_string();
+    h.keys(":2w<CR>");
+    let message = "Use ! to write partial buffer";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+    h.keys(":2w!<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "def\n");
+}
+
+#[test]
+fn write_quit() {
+    let path = temp_path("wq");
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys(":wq<CR>");
+    assert!(h.quit);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abc\n");
+}
+
+#[test]
+fn exit_writes_only_changes() {
+    let path = temp_path("exit");
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys(":x<CR>");
+    assert!(h.quit);
+    assert!(!path.exists());
+
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys("ix<Esc>:x<CR>");
+    assert!(h.quit);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\n");
+}
+
+#[test]
+fn update() {
+    let path = temp_path("update");
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys(":up<CR>");
+    assert!(!path.exists());
+    h.keys("ix<Esc>:up<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\n");
+    assert!(!h.editor.modified());
+}
+
+#[test]
+fn save_as() {
+    let path = temp_path("saveas");
+    let mut h = Harness::new("abc");
+    h.keys(&format!("ix<Esc>:sav {}<CR>", path.display()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\n");
+    assert_eq!(h.editor.file.name, path.display().to_string());
+    assert!(!h.editor.modified());
+    h.keys(&format!(":saveas {}<CR>", path.display()));
+    let message = "File exists (add ! to override)";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+}
+
+#[test]
+fn save_as_renames_after_write() {
+    let mut h = Harness::new("fn main() {}");
+    let name = h.editor.file.name.clone();
+    let missing = temp_path("saveas-missing").join("main.rs");
+    h.keys(&format!(":sav {}<CR>", missing.display()));
+    assert!(h
+        .editor
+        .message
+        .as_deref()
+        .unwrap()
+        .starts_with("Cannot write"));
+    assert_eq!(h.editor.file.name, name);
+    assert_eq!(h.editor.file.filetype(), None);
+
+    let path = temp_path("saveas.rs");
+    h.keys(&format!(":sav {}<CR>", path.display()));
+    assert_eq!(h.editor.file.name, path.display().to_string());
+    assert_eq!(h.editor.file.filetype(), Some("rust"));
+    assert!(h.editor.file.highlighter.is_some());
+}
+
+#[test]
+fn go_to_line() {
+    let mut h = Harness::new("abc\ndef\nghi");
+    h.keys("l:3<CR>");
+    assert_eq!(h.cursor(), (2, 0));
+    h.keys(":.-1<CR>");
+    assert_eq!(h.cursor(), (1, 0));
+}
+
+#[test]
+fn modified_file() {
+    let path = temp_path("modified");
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys("ix<Esc>");
+    assert_eq!(h.editor.status(), format!("{} [+]", path.display()));
+    h.keys("q");
+    assert!(!h.quit);
+    let message = "No write since last change (add ! to override)";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+    h.keys(":w<CR>l");
+    assert_eq!(h.editor.status(), path.display().to_string());
+    h.keys("q");
+    assert!(h.quit);
+}
+
+#[test]
+fn write_keeps_permissions() {
+    let path = temp_path("permissions");
+    fs::write(&path, "abc").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys("ix<Esc>:w<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\n");
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+    let dir = fs::read_dir(env::temp_dir()).unwrap();
+    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+    let leftover = dir.filter_map(|entry| entry.ok()).any(|entry| {
+        entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&format!(".{}", name))
+    });
+    assert!(!leftover);
+}
+
+#[test]
+fn write_backup() {
+    let path = temp_path("backup");
+    let backup = PathBuf::from(format!("{}.bak", path.display()));
+    fs::write(&path, "abc").unwrap();
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys(":set bk bex=.bak<CR>ix<Esc>:w<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\n");
+    assert_eq!(fs::read_to_string(&backup).unwrap(), "abc");
+}
+
+#[test]
+fn write_through_symlink() {
+    let target = temp_path("target");
+    let link = temp_path("link");
+    fs::write(&target, "abc").unwrap();
+    symlink(&target, &link).unwrap();
+    let mut h = Harness::new("abc");
+    h.editor.file.name = link.display().to_string();
+    h.keys("ix<Esc>:w<CR>");
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "xabc\n");
+}
+
+#[test]
+fn write_error() {
+    let path = temp_path("readonly");
+    fs::write(&path, "abc").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o444)).unwrap();
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys("ix<Esc>:w<CR>");
+    let message = format!("Cannot write \"{}\": file is read-only", path.display());
+    assert_eq!(h.editor.message, Some(message));
+    assert!(h.editor.modified());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abc");
+}
+
+#[test]
+fn force_write_readonly_file() {
+    let path = temp_path("force-readonly");
+    fs::write(&path, "abc").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o444)).unwrap();
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys("ix<Esc>:w!<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\n");
+    assert!(!h.editor.modified());
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o444);
+}
+
+#[test]
+fn keep_dos_format() {
+    let path = temp_path("dos");
+    let mut h = Harness::new("abc\r\ndef\r\n");
+    h.editor.file.name = path.display().to_string();
+    assert_eq!(h.buffer(), "abc\ndef");
+    assert_eq!(h.editor.string("fileformat"), "dos");
+    h.keys(":w<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abc\r\ndef\r\n");
+    let message = format!("\"{}\" [dos] 2L, 10B written", path.display());
+    assert_eq!(h.editor.message, Some(message));
+}
+
+#[test]
+fn mixed_line_endings_are_unix() {
+    let mut h = Harness::new("abc\r\ndef\n");
+    assert_eq!(h.buffer(), "abc\r\ndef");
+    assert_eq!(h.editor.string("fileformat"), "unix");
+    h.keys("j");
+    assert_eq!(h.screen().lines().next(), Some("abc^M"));
+}
+
+#[test]
+fn keep_missing_newline() {
+    let path = temp_path("noeol");
+    let mut h = Harness::new("abc\ndef");
+    h.editor.file.name = path.display().to_string();
+    assert!(!h.editor.flag("endofline"));
+    assert!(!h.editor.modified());
+    h.keys(":set nofixeol<CR>:w<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abc\ndef");
+    h.keys(":set eol<CR>");
+    assert!(h.editor.modified());
+    h.keys(":w<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abc\ndef\n");
+}
+
+#[test]
+fn round_trip_empty_line() {
+    let path = temp_path("emptyline");
+    fs::write(&path, "\n").unwrap();
+    let mut h = Harness::new("");
+    let name = path.display().to_string();
+    h.keys(&format!(":e {}<CR>", name));
+    let message = format!("\"{}\" 1L, 1B", name);
+    assert_eq!(h.editor.message, Some(message));
+    assert!(!h.editor.modified());
+    h.keys(":w<CR>");
+    assert_eq!(fs::read(&path).unwrap(), b"\n");
+    let message = format!("\"{}\" 1L, 1B written", name);
+    assert_eq!(h.editor.message, Some(message));
+
+    fs::write(&path, "").unwrap();
+    h.keys(":e!<CR>");
+    let message = format!("\"{}\" 0L, 0B", name);
+    assert_eq!(h.editor.message, Some(message));
+    h.keys(":w<CR>");
+    assert_eq!(fs::read(&path).unwrap(), b"");
+    let message = format!("\"{}\" 0L, 0B written", name);
+    assert_eq!(h.editor.message, Some(message));
+    h.keys("ix<BS><Esc>:w<CR>");
+    assert_eq!(fs::read(&path).unwrap(), b"\n");
+}
+
+#[test]
+fn convert_format() {
+    let path = temp_path("convert");
+    let mut h = Harness::new("abc\ndef\n");
+    h.editor.file.name = path.display().to_string();
+    h.keys(":set ff=dos<CR>");
+    assert!(h.editor.modified());
+    h.keys(":set ff=unix<CR>");
+    assert!(!h.editor.modified());
+    h.keys(":set ff=mac<CR>:w<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abc\rdef\r");
+    assert!(!h.editor.modified());
+    h.keys(":set ff=foo<CR>");
+    let message = "Invalid argument: fileformat=foo";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+}
+
+#[test]
+fn read_mac_format() {
+    let h = Harness::new("abc\rdef\r");
+    assert_eq!(h.buffer(), "abc\ndef");
+    assert_eq!(h.editor.string("fileformat"), "mac");
+}
+
+#[test]
+fn keep_latin1_encoding() {
+    let path = temp_path("latin1");
+    fs::write(&path, b"caf\xe9\n").unwrap();
+    let mut h = Harness::new("");
+    h.editor
+        .open_file(&path.display().to_string(), None)
+        .unwrap();
+    assert_eq!(h.buffer(), "café");
+    assert_eq!(h.editor.string("fileencoding"), "latin1");
+    h.keys("i!<Esc>:w<CR>");
+    assert_eq!(fs::read(&path).unwrap(), b"!caf\xe9\n");
+    let message = format!("\"{}\" [latin1] 1L, 6B written", path.display());
+    assert_eq!(h.editor.message, Some(message));
+}
+
+#[test]
+fn edit_non_ascii_text() {
+    let mut h = Harness::new("café\nnaïve");
+    h.keys("$hix<Esc>");
+    assert_eq!(h.buffer(), "cafxé\nnaïve");
+    assert_eq!(h.cursor(), (0, 4));
+    h.keys("a<BS><BS>ñ<Esc>");
+    assert_eq!(h.buffer(), "cafñ\nnaïve");
+    assert_eq!(h.cursor(), (0, 5));
+    h.keys("jhhi-<Esc>");
+    assert_eq!(h.buffer(), "cafñ\nna-ïve");
+    h.keys("ll");
+    assert_eq!(h.cursor(), (1, 6));
+    h.keys("hi<BS><Esc>");
+    assert_eq!(h.buffer(), "cafñ\nna-ve");
+}
+
+#[test]
+fn keep_utf16_bom() {
+    let path = temp_path("utf16");
+    fs::write(&path, b"\xff\xfea\0b\0\n\0").unwrap();
+    let mut h = Harness::new("");
+    h.editor
+        .open_file(&path.display().to_string(), None)
+        .unwrap();
+    assert_eq!(h.buffer(), "ab");
+    assert_eq!(h.editor.string("fileencoding"), "utf-16le");
+    assert!(h.editor.flag("bomb"));
+    h.keys("a<BS><Esc>:w<CR>");
+    assert_eq!(fs::read(&path).unwrap(), b"\xff\xfeb\0\n\0");
+    h.keys(":set nobomb<CR>");
+    assert!(h.editor.modified());
+    h.keys(":set fenc=utf-16<CR>:w<CR>");
+    assert_eq!(fs::read(&path).unwrap(), b"\0b\0\n");
+    h.keys(":e<CR>");
+    assert_eq!(h.buffer(), "b");
+    assert_eq!(h.editor.string("fileencoding"), "utf-16");
+}
+
+#[test]
+fn edit_with_encoding() {
+    let path = temp_path("edit-enc");
+    fs::write(&path, "é\n").unwrap();
+    let mut h = Harness::new("");
+    let name = path.display().to_string();
+    h.keys(&format!(":e {}<CR>", name));
+    assert_eq!(h.buffer(), "é");
+    h.keys(":e ++enc=latin1<CR>");
+    assert_eq!(h.buffer(), "Ã©");
+    let message = format!("\"{}\" [latin1] 1L, 3B", name);
+    assert_eq!(h.editor.message, Some(message));
+    h.keys(":e ++enc=foo<CR>");
+    assert_eq!(h.editor.message.as_deref(), Some("Invalid encoding: foo"));
+    h.keys("ix<Esc>:e<CR>");
+    let message = "No write since last change (add ! to override)";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+    h.keys(":e!<CR>");
+    assert_eq!(h.buffer(), "é");
+}
+
+#[test]
+fn unconvertible_text() {
+    let path = temp_path("unconvertible");
+    let mut h = Harness::new("€\n");
+    h.editor.file.name = path.display().to_string();
+    h.keys(":set fenc=latin1<CR>:w<CR>");
+    assert!(!path.exists());
+    assert!(h.editor.modified());
+    let message = h.editor.message.clone().unwrap();
+    assert!(
+        message.contains("cannot convert '€' to latin1"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn start_without_file() {
+    let mut h = Harness {
+        editor: Editor::builtin((40, 6)),
+        backend: MemoryBackend::new(40, 6),
+        quit: false,
+    };
+    h.keys("j");
+    assert_eq!(h.buffer(), "");
+    assert_eq!(h.screen().lines().last(), Some("[No Name]"));
+    h.keys("iabc<Esc>:w<CR>");
+    assert_eq!(h.editor.message.as_deref(), Some("No file name"));
+    assert_eq!(h.editor.status(), "No file name");
+    h.editor.message = None;
+    assert_eq!(h.editor.status(), "[No Name] [+]");
+}
+
+#[test]
+fn edit_new_file() {
+    let path = temp_path("new");
+    let name = path.display().to_string();
+    let mut h = Harness::new("");
+    h.keys(&format!(":e {}<CR>", name));
+    assert_eq!(h.editor.message, Some(format!("\"{}\" [New]", name)));
+    assert!(!path.exists());
+    h.keys("iabc<Esc>:w<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abc\n");
+}
+
+fn parse_args(args: &[&str]) -> Result<Command, String> {
+    cli::parse("viprust", args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn command_line_arguments() {
+    let args = parse_args(&[
+        "-R", "+3", "-c", "set ts=4", "--cmd", "map x y", "a.rs", "b.rs",
+    ]);
+    let expected = Args {
+        files: vec!["a.rs".to_string(), "b.rs".to_string()],
+        readonly: true,
+        pre_commands: vec!["map x y".to_string()],
+        commands: vec!["3".to_string(), "set ts=4".to_string()],
+        ..Args::default()
+    };
+    assert_eq!(args, Ok(Command::Edit(expected)));
+
+    let Ok(Command::Edit(args)) = parse_args(&["-O2", "-u", "NONE", "--", "-R", "+"]) else {
+        panic!("arguments not parsed");
+    };
+    assert_eq!(args.split, Some((Split::Vertical, Some(2))));
+    assert_eq!(args.files, ["-R", "+"]);
+    assert_eq!(args.config, Some(PathBuf::from("NONE")));
+
+    let Ok(Command::Edit(args)) = cli::parse("/usr/bin/view", ["+", "-"].map(String::from)) else {
+        panic!("arguments not parsed");
+    };
+    assert!(args.readonly);
+    assert_eq!(args.commands, ["$"]);
+    assert_eq!(args.files, ["-"]);
+
+    assert_eq!(parse_args(&["--help"]), Ok(Command::Help));
+    assert_eq!(parse_args(&["--version", "-x"]), Ok(Command::Version));
+    let err = "Unknown option argument: \"-x\"";
+    assert_eq!(parse_args(&["-x"]), Err(err.to_string()));
+    let err = "Argument missing after: \"-c\"";
+    assert_eq!(parse_args(&["-c"]), Err(err.to_string()));
+    let err = "Garbage after option argument: \"-ox\"";
+    assert_eq!(parse_args(&["-ox"]), Err(err.to_string()));
+}
+
+#[test]
+fn startup_commands() {
+    let mut h = Harness::new("fn a\nfn b\nlet c\nfn d");
+    assert!(!h.editor.run_command("/^let"));
+    assert_eq!(h.cursor(), (2, 0));
+    h.editor.run_command("/fn/");
+    assert_eq!(h.cursor(), (3, 0));
+    h.editor.run_command("/fn [ab]");
+    assert_eq!(h.cursor(), (0, 0));
+    h.editor.run_command("/nothing");
+    assert_eq!(
+        h.editor.message.as_deref(),
+        Some("Pattern not found: nothing")
+    );
+    h.editor.run_command("/(");
+    assert_eq!(h.editor.message.as_deref(), Some("Invalid pattern: ("));
+    assert!(h.editor.run_command("q"));
+}
+
+#[test]
+fn startup_config() {
+    let source = "[options]\nshiftwidth = 4\ntabstop = 4\n";
+    let config = config::parse(Path::new("config.toml"), source)
+        .ok()
+        .unwrap();
+    let theme = theme::builtin("default").unwrap();
+    let mut editor = Editor::new(Vec::new(), theme, config, None, (40, 6));
+    assert!(!editor.run_command("set sw=2 ts=2"));
+    assert!(editor.apply_startup_config().is_empty());
+    assert_eq!(editor.number("shiftwidth"), 4);
+    assert_eq!(editor.number("tabstop"), 4);
+}
+
+#[test]
+fn startup_command_scrolls() {
+    let text: Vec<_> = (1..=200).map(|i| format!("line {}", i)).collect();
+    let mut h = Harness::new(&text.join("\n"));
+    h.editor.run_command("100");
+    let frame = h.editor.draw();
+    let rows: Vec<_> = (0..5).map(|y| frame.grid.row_text(y)).collect();
+    assert!(rows.contains(&"line 100".to_string()), "{:?}", rows);
+    assert!(!rows.contains(&"line 1".to_string()));
+    h.editor.run_command("$");
+    let frame = h.editor.draw();
+    assert_eq!(frame.grid.row_text(4), "line 200");
+}
+
+#[test]
+fn broken_user_theme() {
+    let dir = temp_path("config");
+    fs::create_dir_all(dir.join("colors")).unwrap();
+    let path = dir.join("colors").join("default.toml");
+    fs::write(&path, "[Normal\n").unwrap();
+    let err = theme::load("default", Some(&dir)).err().unwrap();
+    assert!(err.starts_with(&format!("{}: ", path.display())), "{}", err);
+    assert_eq!(theme::load("default", None).unwrap().name, "default");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn theme_links() {
+    let source = "[A]\nfg = 1\n[B]\nlink = \"A\"\n[C]\nlink = \"B\"\n";
+    let theme = Theme::parse("t", source, false).unwrap();
+    assert_eq!(theme.style("C").foreground_color, Some(Color::AnsiValue(1)));
+    assert_eq!(theme.style("B"), theme.style("A"));
+    let source = "[A]\nlink = \"B\"\n[B]\nlink = \"C\"\n[C]\nlink = \"A\"\n";
+    let err = Theme::parse("t", source, false).err().unwrap();
+    assert!(err.ends_with(": circular `link`"), "{}", err);
+    let source = "[A]\nlink = \"A\"\n";
+    assert!(Theme::parse("t", source, false).is_err());
+}
+
+#[test]
+fn theme_colors() {
+    let color = |source: &str, truecolor| {
+        let theme = Theme::parse("t", source, truecolor).unwrap();
+        theme.style("Normal").foreground_color
+    };
+    let red = "[Normal]\nfg = \"#ff0000\"\n";
+    assert_eq!(color(red, true), Some(Color::Rgb { r: 255, g: 0, b: 0 }));
+    assert_eq!(color(red, false), Some(Color::AnsiValue(196)));
+    let gray = "[Normal]\nfg = \"#808080\"\n";
+    assert_eq!(color(gray, false), Some(Color::AnsiValue(244)));
+    let both = "[Normal]\nfg = \"#ff0000\"\nctermfg = 9\n";
+    assert_eq!(color(both, false), Some(Color::AnsiValue(9)));
+    assert_eq!(color(both, true), Some(Color::Rgb { r: 255, g: 0, b: 0 }));
+    let err = Theme::parse("t", "[Normal]\nfg = \"#ff00\"\n", false).err();
+    assert_eq!(err.as_deref(), Some("Normal: invalid color `#ff00`"));
+}
+
+#[test]
+fn colorscheme_command() {
+    let mut h = Harness::new("abc");
+    h.keys(":colo<CR>");
+    assert_eq!(h.editor.message.as_deref(), Some("default"));
+    h.keys(":colorscheme gruvbox<CR>:colo<CR>");
+    assert_eq!(h.editor.message.as_deref(), Some("gruvbox"));
+    h.keys(":colo ../x<CR>");
+    let message = "Invalid color scheme name '../x'";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+    h.keys(":colo nothing<CR>");
+    let message = "Cannot find color scheme 'nothing'";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+    assert_eq!(h.editor.theme.name, "gruvbox");
+
+    let dir = temp_path("colors");
+    fs::create_dir_all(dir.join("colors")).unwrap();
+    fs::write(dir.join("colors").join("mine.toml"), "[Normal]\nfg = 3\n").unwrap();
+    h.editor.config_dir = Some(dir.clone());
+    h.keys(":colo mine<CR>");
+    assert_eq!(h.editor.theme.name, "mine");
+    let normal = h.editor.theme.style("Normal");
+    assert_eq!(normal.foreground_color, Some(Color::AnsiValue(3)));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+fn token(text: &str, class: TokenClass) -> (String, TokenClass) {
+    (text.to_string(), class)
+}
+
+#[test]
+fn highlight_nested_comments() {
+    use TokenClass::*;
+    let mut h = Harness::rust("/* a /* b */ c */ fn\nx /* one\n/* two */\nthree */ y");
+    let comment = "/* a /* b */ c */";
+    assert_eq!(h.tokens(0), [token(comment, Comment), token("fn", Keyword)]);
+    assert_eq!(h.tokens(1), [token("/* one", Comment)]);
+    assert_eq!(h.tokens(2), [token("/* two */", Comment)]);
+    assert_eq!(h.tokens(3), [token("three */", Comment)]);
+
+    let mut h = Harness::rust("fn a() {}\n/* open\nfn b() {}\n\nlet c");
+    assert_eq!(h.tokens(2), [token("fn b() {}", Comment)]);
+    assert_eq!(h.tokens(4), [token("let c", Comment)]);
+}
+
+#[test]
+fn highlight_raw_strings() {
+    use TokenClass::*;
+    let mut h = Harness::rust("let s = r#\"a \"b\" c\"#;\nr##\"x\n\"# y\n\"## fn");
+    assert_eq!(h.tokens(0)[1], token("r#\"a \"b\" c\"#", String));
+    assert_eq!(h.tokens(1), [token("r##\"x", String)]);
+    assert_eq!(h.tokens(2), [token("\"# y", String)]);
+    assert_eq!(h.tokens(3), [token("\"##", String), token("fn", Keyword)]);
+}
+
+#[test]
+fn highlight_after_edits() {
+    use TokenClass::*;
+    let mut h = Harness::rust("fn a\nb\n*/ c\nfn d");
+    assert_eq!(h.tokens(1), []);
+    assert_eq!(h.tokens(3), [token("fn", Keyword)]);
+    h.keys("ji/*<Esc>");
+    assert_eq!(h.tokens(1), [token("/*b", Comment)]);
+    assert_eq!(h.tokens(2), [token("*/", Comment)]);
+    assert_eq!(h.tokens(3), [token("fn", Keyword)]);
+    h.keys("jj$i/*<Esc>");
+    assert_eq!(h.tokens(3), [token("fn", Keyword), token("/*", Comment)]);
+    h.keys("kk0lli<BS><BS><Esc>");
+    assert_eq!(h.buffer(), "fn a\nb\n*/ c\nfn d/*");
+    assert_eq!(h.tokens(1), []);
+    assert_eq!(h.tokens(2), []);
+}
+
+#[test]
+fn user_syntaxes() {
+    let dir = temp_path("syntaxes");
+    fs::create_dir_all(dir.join("syntax")).unwrap();
+    let bad = dir.join("syntax").join("bad.toml");
+    fs::write(
+        &bad,
+        "name = \"bad\"\n[[rules]]\nclass = \"comment\"\nmatch = \"(\"\n",
+    )
+    .unwrap();
+    let good =
+        "name = \"rust\"\nextensions = [\"rs\"]\n[[rules]]\nclass = \"comment\"\nstart = \"%\"\n";
+    fs::write(dir.join("syntax").join("rust.toml"), good).unwrap();
+    let (syntaxes, errors) = highlight::load_syntaxes(Some(&dir));
+    assert_eq!(errors.len(), 1);
+    let err = format!("{}: rule 1: invalid pattern `(`", bad.display());
+    assert!(errors[0].starts_with(&err), "{}", errors[0]);
+    assert!(syntaxes.iter().all(|syntax| syntax.name != "bad"));
+    assert_eq!(
+        syntaxes
+            .iter()
+            .filter(|syntax| syntax.name == "rust")
+            .count(),
+        1
+    );
+
+    let mut h = Harness::new("");
+    h.editor.syntaxes = syntaxes;
+    h.editor.load_text("test.rs", "fn a % b");
+    let comment = token("% b", TokenClass::Comment);
+    assert_eq!(h.tokens(0), [comment]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn readonly_file() {
+    let path = temp_path("readonly-option");
+    let mut h = Harness::new("abc");
+    h.editor.file.name = path.display().to_string();
+    h.keys(":setl ro<CR>:w<CR>");
+    let message = "'readonly' option is set (add ! to override)";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+    h.editor.message = None;
+    assert!(h.editor.status().ends_with(" [RO]"));
+    h.keys(":w!<CR>");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "abc\n");
+}
+
+#[test]
+fn buffer_list() {
+    let (first, second) = (temp_path("buffer-a"), temp_path("buffer-b"));
+    fs::write(&first, "a1\na2\n").unwrap();
+    fs::write(&second, "b1\n").unwrap();
+    let (first, second) = (first.display().to_string(), second.display().to_string());
+    let mut h = Harness::new("");
+    h.keys(&format!(":e {}<CR>j", first));
+    h.keys(&format!(":e {}<CR>", second));
+    assert_eq!(h.buffer(), "b1");
+    assert_eq!(h.editor.file.number, 3);
+    h.keys("<C-^>");
+    assert_eq!(h.buffer(), "a1\na2");
+    assert_eq!(h.cursor(), (1, 0));
+    h.keys("ix<Esc>:ls<CR>");
+    let listing = format!(
+        "1    \"test.txt\" line 1 | 2 %+ \"{}\" line 2 | 3 #  \"{}\" line 1",
+        first, second
+    );
+    assert_eq!(h.editor.message, Some(listing));
+
+    h.keys(":bn<CR>");
+    assert_eq!(h.buffer(), "b1");
+    h.keys(":bn<CR>");
+    assert_eq!(h.editor.file.number, 1);
+    h.keys(":bp<CR>:b 2<CR>");
+    assert_eq!(h.buffer(), "a1\nxa2");
+    h.keys(":b buffer<CR>");
+    assert_eq!(
+        h.editor.message.as_deref(),
+        Some("More than one match for buffer")
+    );
+    h.keys(":b buffer-b<CR>");
+    assert_eq!(h.buffer(), "b1");
+    h.keys(":b 9<CR>");
+    assert_eq!(h.editor.message.as_deref(), Some("Buffer 9 does not exist"));
+}
+
+#[test]
+fn delete_buffers() {
+    let path = temp_path("delete");
+    let name = path.display().to_string();
+    let mut h = Harness::new("abc");
+    h.keys(&format!(":e {}<CR>", name));
+    h.keys("ix<Esc>:q<CR>");
+    let message = "No write since last change (add ! to override)";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+    h.keys("<C-^>:q<CR>");
+    let message = format!("No write since last change for buffer \"{}\"", name);
+    assert_eq!(h.editor.message, Some(message));
+    assert!(!h.quit);
+    h.keys(":bd 2<CR>");
+    let message = "No write since last change for buffer 2 (add ! to override)";
+    assert_eq!(h.editor.message.as_deref(), Some(message));
+    h.keys(":bd! 2<CR>");
+    assert_eq!(h.editor.buffer_numbers(), [1]);
+    assert_eq!(h.editor.alternate, None);
+    h.keys(":bd<CR>");
+    assert_eq!(h.editor.buffer_numbers(), [3]);
+    assert_eq!(h.buffer(), "");
+    h.keys("<C-^>");
+    assert_eq!(h.editor.message.as_deref(), Some("No alternate file"));
+    h.keys(":q<CR>");
+    assert!(h.quit);
+}
+
+#[test]
+fn reload_file() {
+    let path = temp_path("reload");
+    fs::write(&path, "abc\ndef\n").unwrap();
+    let mut h = Harness::new("");
+    h.keys(&format!(":e {}<CR>jix<Esc>", path.display()));
+    assert_eq!(h.buffer(), "abc\nxdef");
+    h.keys(":e!<CR>");
+    assert_eq!(h.buffer(), "abc\ndef");
+    assert_eq!(h.cursor(), (1, 0));
+    assert!(!h.editor.modified());
+    assert_eq!(h.editor.file.number, 2);
+}
+
+#[test]
+fn split_windows() {
+    let mut h = Harness::with_size("one\ntwo\nthree", 20, 8);
+    h.keys("j:sp<CR>");
+    assert_eq!(h.editor.layout.windows(), [2, 1]);
+    assert_eq!(h.editor.window, 2);
+    h.keys("j");
+    let screen = [
+        "one", "two", "three", "test.txt", "one", "two", "test.txt", "",
+    ];
+    assert_eq!(h.screen(), screen.join("\n"));
+    assert_eq!(h.screen_cursor(), (0, 2));
+    h.keys("<C-w>jiX<Esc>");
+    assert_eq!(h.cursor(), (1, 1));
+    assert_eq!(h.buffer(), "one\nXtwo\nthree");
+    assert_eq!(h.screen().lines().nth(1), Some("Xtwo"));
+    assert_eq!(h.screen().lines().nth(3), Some("test.txt [+]"));
+    h.keys("<C-w>k");
+    assert_eq!((h.editor.window, h.cursor()), (2, (2, 0)));
+    h.keys("<C-w>+");
+    assert_eq!(h.editor.window_rects().0[0].1.height, 5);
+    h.keys("<C-w>=<C-w>w");
+    assert_eq!(h.editor.window, 1);
+    assert_eq!(h.editor.window_rects().0[0].1.height, 4);
+}
+
+#[test]
+fn vertical_split() {
+    let mut h = Harness::with_size("abcdefghijkl\nxyz", 21, 4);
+    h.keys(":vs<CR>");
+    let screen = [
+        "abcdefghij|abcdefghij",
+        "xyz       |xyz",
+        "test.txt   test.txt",
+        "",
+    ];
+    assert_eq!(h.screen(), screen.join("\n"));
+    h.keys("<C-w>>");
+    assert_eq!(h.screen().lines().next(), Some("abcdefghijk|abcdefghi"));
+    h.keys("<C-w>l");
+    assert_eq!(h.editor.window, 1);
+    h.keys(":vnew<CR>");
+    assert_eq!(h.editor.layout.windows(), [2, 3, 1]);
+    assert_eq!(h.buffer(), "");
+    h.keys("<C-w>h");
+    assert_eq!(h.editor.window, 2);
+}
+
+#[test]
+fn close_windows() {
+    let mut h = Harness::with_size("abc", 40, 10);
+    h.keys("<C-w>s<C-w>v");
+    assert_eq!(h.editor.layout.windows(), [3, 2, 1]);
+    h.keys(":close<CR>");
+    assert_eq!(h.editor.layout.windows(), [2, 1]);
+    assert_eq!(h.editor.window, 2);
+    h.keys("<C-w>s:only<CR>");
+    assert_eq!(h.editor.layout.windows(), [4]);
+    h.keys("<C-w>c");
+    assert_eq!(
+        h.editor.message.as_deref(),
+        Some("Cannot close last window")
+    );
+    h.keys("<C-w>s:q<CR>");
+    assert!(!h.quit);
+    assert_eq!(h.editor.layout.windows(), [4]);
+    assert_eq!(h.screen().lines().last(), Some("test.txt"));
+    h.keys(":q<CR>");
+    assert!(h.quit);
+}
+
+#[test]
+fn windows_on_different_buffers() {
+    let path = temp_path("window");
+    fs::write(&path, "other\n").unwrap();
+    let name = path.display().to_string();
+    let mut h = Harness::with_size("abc", 40, 8);
+    h.keys(&format!(":sp {}<CR>", name));
+    assert_eq!(h.buffer(), "other");
+    h.keys("<C-w>j");
+    assert_eq!(h.buffer(), "abc");
+    assert_eq!(h.screen().lines().nth(3), Some(name.as_str()));
+    h.keys(&format!(":bd {}<CR>", name));
+    assert_eq!(h.editor.layout.windows(), [1]);
+    h.keys("<C-w>s<C-w>s<C-w>s");
+    assert_eq!(h.editor.layout.windows().len(), 3);
+    assert_eq!(h.editor.message.as_deref(), Some("Not enough room"));
+}
+
+#[test]
+fn window_cursor_after_change_in_other_window() {
+    let mut h = Harness::with_size("aé", 20, 8);
+    h.keys("$:sp<CR>$a<BS><BS>éé<Esc><C-w>jl");
+    assert_eq!(h.buffer(), "éé");
+    assert_eq!(h.cursor(), (0, 4));
+    let mut h = Harness::with_size("aé", 20, 8);
+    h.keys("$:sp<CR>$a<BS><BS>éé<Esc><C-w>jix<Esc>");
+    assert_eq!(h.buffer(), "éxé");
+}
+
+#[test]
+fn tab_pages() {
+    let mut h = Harness::with_size("abc", 30, 6);
+    h.keys(":tabnew<CR>");
+    assert_eq!((h.editor.tab, h.editor.tab_count()), (1, 2));
+    assert_eq!(h.buffer(), "");
+    let screen = [" test.txt  [No Name]", "", "~", "~", "~", "[No Name]"];
+    assert_eq!(h.screen(), screen.join("\n"));
+    assert_eq!(h.screen_cursor(), (0, 1));
+    h.keys("iX<Esc><C-w>v");
+    assert_eq!(h.screen().lines().next(), Some(" test.txt  2+ [No Name]"));
+    h.keys("gt");
+    assert_eq!(h.editor.tab, 0);
+    assert_eq!(h.buffer(), "abc");
+    assert_eq!(h.screen().lines().nth(1), Some("abc"));
+    h.keys("gTgT");
+    assert_eq!(h.editor.tab, 0);
+    h.keys(":tabnext 2<CR>");
+    assert_eq!(h.editor.layout.windows().len(), 2);
+    h.keys(":tabclose<CR>");
+    assert_eq!((h.editor.tab, h.editor.tab_count()), (0, 1));
+    assert_eq!(h.buffer(), "abc");
+    assert_eq!(h.screen().lines().next(), Some("abc"));
+    h.keys(":tabclose<CR>");
+    assert_eq!(
+        h.editor.message.as_deref(),
+        Some("Cannot close last tab page")
+    );
+}
+
+#[test]
+fn close_tab_pages() {
+    let mut h = Harness::new("abc");
+    h.keys(":tabnew<CR>:tabnew<CR>:tabnext 1<CR>");
+    assert_eq!((h.editor.tab, h.editor.tab_count()), (0, 3));
+    h.keys(":tabe other.txt<CR>");
+    assert_eq!(h.editor.tab, 1);
+    h.keys(":q<CR>");
+    assert!(!h.quit);
+    assert_eq!((h.editor.tab, h.editor.tab_count()), (1, 3));
+    h.keys(":bd 3<CR>");
+    assert_eq!((h.editor.tab, h.editor.tab_count()), (1, 2));
+    h.keys(":tabonly<CR>");
+    assert_eq!(h.editor.tab_count(), 1);
+    assert_eq!(h.buffer(), "");
+    h.keys(":q<CR>");
+    assert!(h.quit);
+}
+
+#[test]
+fn line_numbers() {
+    let text = (1..=12).map(|n| format!("line {}", n)).collect::<Vec<_>>();
+    let mut h = Harness::with_size(&text.join("\n"), 20, 5);
+    h.keys(":set nu<CR>jj");
+    let screen = ["  1 line 1", "  2 line 2", "  3 line 3", "  4 line 4"];
+    assert_eq!(h.screen().lines().take(4).collect::<Vec<_>>(), screen);
+    assert_eq!(h.screen_cursor(), (4, 2));
+    h.keys(":set rnu<CR>");
+    let screen = ["  2 line 1", "  1 line 2", "3   line 3", "  1 line 4"];
+    assert_eq!(h.screen().lines().take(4).collect::<Vec<_>>(), screen);
+    h.keys(":set nonu nuw=2<CR>");
+    let screen = [" 2 line 1", " 1 line 2", " 0 line 3", " 1 line 4"];
+    assert_eq!(h.screen().lines().take(4).collect::<Vec<_>>(), screen);
+    h.keys(":set nornu<CR>");
+    assert_eq!(h.screen().lines().next(), Some("line 1"));
+    h.keys(":set nuw=0<CR>");
+    assert_eq!(
+        h.editor.message.as_deref(),
+        Some("Argument must be positive: numberwidth=0")
+    );
+}
+
+#[test]
+fn line_numbers_scroll() {
+    let mut h = Harness::with_size("abcdefghijklmnopqrstuvwxyz", 20, 3);
+    h.keys(":set nu sidescroll=1<CR>$");
+    assert_eq!(h.editor.file.col_scroll_pos, 11);
+    assert_eq!(h.screen().lines().next(), Some("  1 lmnopqrstuvwxyz"));
+    assert_eq!(h.screen_cursor(), (19, 0));
+}
+
+#[test]
+fn sign_column() {
+    let mut h = Harness::with_size("one\ntwo", 20, 4);
+    h.editor.file.lines[1].sign = Some(Sign {
+        text: ">>".to_string(),
+        group: "Error".to_string(),
+    });
+    h.keys(":set nu<CR>j");
+    assert_eq!(
+        h.screen().lines().take(2).collect::<Vec<_>>(),
+        ["    1 one", ">>  2 two"]
+    );
+    assert_eq!(h.screen_cursor(), (6, 1));
+    h.keys(":set scl=no<CR>");
+    assert_eq!(h.screen().lines().nth(1), Some("  2 two"));
+    h.editor.file.lines[1].sign = None;
+    h.keys(":set scl=yes<CR>");
+    assert_eq!(h.screen().lines().nth(1), Some("    2 two"));
+    h.keys(":set scl=maybe<CR>");
+    assert_eq!(
+        h.editor.message.as_deref(),
+        Some("Invalid argument: signcolumn=maybe")
+    );
+}
+
+#[test]
+fn wrap_lines() {
+    let mut h = Harness::with_size("abcdefghijklmnopqrstuvwxyz0123456789\nshort", 10, 6);
+    h.keys(":set wrap<CR>$");
+    let screen = ["abcdefghij", "klmnopqrst", "uvwxyz0123", "456789", "short"];
+    assert_eq!(h.screen().lines().take(5).collect::<Vec<_>>(), screen);
+    assert_eq!(h.editor.file.col_scroll_pos, 0);
+    assert_eq!(h.screen_cursor(), (6, 3));
+    h.keys("g0");
+    assert_eq!(h.cursor(), (0, 30));
+    h.keys("llgk");
+    assert_eq!(h.cursor(), (0, 22));
+    assert_eq!(h.screen_cursor(), (2, 2));
+    h.keys("gjgj");
+    assert_eq!(h.cursor(), (1, 2));
+    h.keys("g$");
+    assert_eq!(h.cursor(), (1, 5));
+    h.keys("gkgkg$");
+    assert_eq!(h.cursor(), (0, 29));
+    h.keys(":set nowrap<CR>");
+    assert_eq!(h.editor.file.col_scroll_pos, 20);
+    h.keys("g0");
+    assert_eq!(h.cursor(), (0, 20));
+    h.keys("g$");
+    assert_eq!(h.cursor(), (0, 29));
+    h.keys("gj");
+    assert_eq!(h.cursor(), (1, 5));
+}
+
+#[test]
+fn wrap_local_tabstop() {
+    let mut h = Harness::with_size("a\tb\tc\td\te\tf\tg", 10, 6);
+    h.keys(":set wrap<CR>:setl ts=2<CR>");
+    assert_eq!(
+        h.screen().lines().take(2).collect::<Vec<_>>(),
+        ["a b c d e", "f g"]
+    );
+    h.keys("g$");
+    assert_eq!(h.cursor(), (0, 9));
+    h.keys(":set nowrap<CR>$g0");
+    assert_eq!(h.cursor(), (0, 4));
+    h.keys("g$");
+    assert_eq!(h.cursor(), (0, 13));
+}
+
+#[test]
+fn wrap_line_taller_than_window() {
+    let mut h = Harness::with_size(&format!("{}\nend", "x".repeat(400)), 20, 6);
+    h.keys(":set wrap<CR>$");
+    assert_eq!(h.cursor(), (0, 400));
+    assert_eq!(h.screen_cursor(), (19, 4));
+    assert_eq!(h.editor.file.col_scroll_pos, 15);
+    assert_eq!(h.screen().lines().nth(4), Some("x".repeat(20).as_str()));
+    h.keys("gkgkgkgkgk");
+    assert_eq!(h.screen_cursor(), (19, 0));
+    assert_eq!(h.editor.file.col_scroll_pos, 14);
+    h.keys("j");
+    assert_eq!(h.cursor(), (1, 3));
+    assert_eq!(h.editor.file.col_scroll_pos, 0);
+    assert_eq!(h.screen_cursor(), (3, 0));
+    h.keys("k0");
+    assert_eq!(h.screen_cursor(), (0, 0));
+    assert_eq!(h.editor.file.col_scroll_pos, 0);
+}
+
+#[test]
+fn linebreak_showbreak() {
+    let mut h = Harness::with_size("  one two three four", 12, 5);
+    h.keys(":set wrap lbr sbr=> bri<CR>");
+    let screen = ["  one two", "  >three", "  >four", "~"];
+    assert_eq!(h.screen().lines().take(4).collect::<Vec<_>>(), screen);
+    h.keys("gjgj");
+    assert_eq!(h.cursor(), (0, 16));
+    assert_eq!(h.screen_cursor(), (3, 2));
+    h.keys(":set nobri nu<CR>");
+    let screen = ["  1   one", "    >two", "    >three"];
+    assert_eq!(h.screen().lines().take(3).collect::<Vec<_>>(), screen);
+}
+
+#[test]
+fn wrap_scrolling() {
+    let mut h = Harness::with_size("x\ny\naaaaaaaaaaaaaaa\nb", 10, 4);
+    h.keys(":set wrap<CR>");
+    assert_eq!(
+        h.screen().lines().take(3).collect::<Vec<_>>(),
+        ["x", "y", "@"]
+    );
+    h.keys("jj");
+    assert_eq!(h.editor.file.row_scroll_pos, 1);
+    assert_eq!(
+        h.screen().lines().take(3).collect::<Vec<_>>(),
+        ["y", "aaaaaaaaaa", "aaaaa"]
+    );
+    h.keys("j");
+    assert_eq!(h.editor.file.row_scroll_pos, 2);
+    assert_eq!(h.screen_cursor(), (0, 2));
+    h.keys("<C-y><C-y>");
+    assert_eq!(h.editor.file.row_scroll_pos, 0);
+    assert_eq!(h.cursor().0, 1);
+}
+
+#[test]
+fn screen_updates_changed_cells() {
+    let mut h = Harness::rust("// a");
+    // Only the cursor moves, so no cells are written.
+    h.keys("$i");
+    assert_eq!(h.backend.updates, vec![]);
+    h.keys("b");
+    assert_eq!(h.backend.updates, vec![(0, 4..5), (5, 8..11)]);
+    h.keys("c");
+    assert_eq!(h.backend.updates, vec![(0, 5..6)]);
+    let cell = h.backend.frame.as_ref().unwrap().grid.get(5, 0);
+    assert_eq!(cell.symbol, 'c');
+    assert_eq!(cell.style, h.editor.theme.text_style("Comment"));
+    assert_ne!(cell.style, h.editor.theme.text_style("Normal"));
+    // Drawing the same frame again writes nothing.
+    let frame = h.editor.draw();
+    h.backend.draw(frame).unwrap();
+    assert_eq!(h.backend.updates, vec![]);
+}